@@ -1,9 +1,11 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use mcp_memex::{handlers, ServerConfig};
+use mcp_memex::{handlers, storage::ExportFormat, ServerConfig};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -20,9 +22,96 @@ struct Args {
     #[arg(long, default_value = "~/.mcp-servers/mcp_memex/lancedb")]
     db_path: String,
 
+    /// Size cap in MB of the persistent embedding cache (0 disables it)
+    #[arg(long, default_value = "1024")]
+    embedding_cache_mb: usize,
+
+    /// Default collection (Lance table) for calls that do not pass one
+    #[arg(long, default_value = "mcp_documents")]
+    collection: String,
+
+    /// Keep table versions younger than this many hours when optimizing
+    #[arg(long, default_value = "168")]
+    version_retention_hours: u64,
+
+    /// Keep deleted rows restorable in the trash for this many hours
+    #[arg(long, default_value = "168")]
+    trash_retention_hours: u64,
+
+    /// Optimize all collections every N minutes while serving (off by default)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    optimize_interval_mins: Option<u64>,
+
+    /// Directory the namespace_export tool writes into and namespace_import reads from
+    /// (the export and import subcommands take any path)
+    #[arg(long, default_value = "~/.mcp-servers/mcp_memex/exports")]
+    export_dir: String,
+
     /// Log level
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Run the JSON-RPC server on stdin/stdout (default)
+    Serve,
+
+    /// Re-embed stored text of --collection with the active embedding provider
+    Reembed {
+        /// Only re-embed this namespace (requires --target-collection)
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Copy into this collection instead of rebuilding --collection in place
+        #[arg(long)]
+        target_collection: Option<String>,
+    },
+
+    /// Compact fragments and prune old versions of --collection, then print
+    /// before/after sizes
+    Optimize {
+        /// Optimize every collection instead of only --collection
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Export a namespace of --collection to a JSONL or Parquet file
+    Export {
+        /// Namespace to export
+        #[arg(long)]
+        namespace: String,
+
+        /// Output file; a .parquet extension selects Parquet unless --format is given
+        #[arg(long)]
+        path: PathBuf,
+
+        /// jsonl or parquet
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Import an export file into --collection
+    Import {
+        /// Export file to read
+        #[arg(long)]
+        path: PathBuf,
+
+        /// jsonl or parquet (inferred from the extension by default)
+        #[arg(long)]
+        format: Option<String>,
+
+        /// Import into this namespace instead of the one recorded in the file
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Embed the text again with the active provider instead of keeping the vectors
+        #[arg(long)]
+        reembed: bool,
+    },
 }
 
 impl Args {
@@ -37,6 +126,14 @@ impl Args {
                 .collect(),
             cache_mb: self.cache_mb,
             db_path: shellexpand::tilde(&self.db_path).to_string(),
+            embedding_cache_mb: self.embedding_cache_mb,
+            collection_name: self.collection,
+            version_retention: Duration::from_secs(self.version_retention_hours * 3600),
+            trash_retention: Duration::from_secs(self.trash_retention_hours * 3600),
+            optimize_interval: self
+                .optimize_interval_mins
+                .map(|mins| Duration::from_secs(mins * 60)),
+            export_dir: shellexpand::tilde(&self.export_dir).to_string(),
             log_level: parse_log_level(&self.log_level),
         }
     }
@@ -70,9 +167,84 @@ async fn main() -> Result<()> {
     info!("Features: {}", args.features);
     info!("Cache: {}MB", args.cache_mb);
     info!("DB Path: {}", config.db_path);
+    info!("Collection: {}", config.collection_name);
 
-    let server = handlers::create_server(config).await?;
-    server.run_stdio().await?;
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let server = handlers::create_server(config).await?;
+            server.run_stdio().await?;
+        }
+        Command::Reembed {
+            namespace,
+            target_collection,
+        } => {
+            let rag = handlers::create_pipeline(config).await?;
+            let report = rag
+                .reembed(None, namespace.as_deref(), target_collection.as_deref())
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::Optimize { all } => {
+            let collection = (!all).then(|| config.collection_name.clone());
+            let rag = handlers::create_pipeline(config).await?;
+            let reports = rag.optimize(collection.as_deref(), None).await?;
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        }
+        Command::Export {
+            namespace,
+            path,
+            format,
+        } => {
+            let format = ExportFormat::resolve(format.as_deref(), &path)?;
+            let rag = handlers::create_pipeline(config).await?;
+            let report = rag
+                .export_namespace(None, &namespace, &path, format)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Command::Import {
+            path,
+            format,
+            namespace,
+            reembed,
+        } => {
+            let format = ExportFormat::resolve(format.as_deref(), &path)?;
+            let rag = handlers::create_pipeline(config).await?;
+            let report = rag
+                .import_namespace(None, &path, format, namespace.as_deref(), reembed)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimize_interval_must_be_positive() {
+        assert!(Args::try_parse_from(["mcp_memex", "--optimize-interval-mins", "0"]).is_err());
+        let args = Args::try_parse_from(["mcp_memex", "--optimize-interval-mins", "5"]).unwrap();
+        assert_eq!(
+            args.into_config().optimize_interval,
+            Some(Duration::from_secs(300))
+        );
+    }
+
+    #[test]
+    fn optimize_covers_every_collection_only_with_all() {
+        let args = Args::try_parse_from(["mcp_memex", "--collection", "foo", "optimize"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Optimize { all: false })
+        ));
+        let args = Args::try_parse_from(["mcp_memex", "optimize", "--all"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Optimize { all: true })
+        ));
+    }
+}
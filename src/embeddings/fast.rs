@@ -0,0 +1,674 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use fastembed::{
+    EmbeddingModel, InitOptionsUserDefined, Pooling, RerankInitOptions, RerankerModel,
+    TextEmbedding, TextInitOptions, TextRerank, TokenizerFiles, UserDefinedEmbeddingModel,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{
+    self,
+    error::{SendTimeoutError, TrySendError},
+};
+use tokio::sync::{oneshot, OnceCell};
+
+use super::{embed_in_batches, env_var, parse_positive, Batching, Embedder, Reranker, TokenLimit};
+
+/// Reranker used when `FASTEMBED_RERANKER_MODEL` is unset.
+pub const FASTEMBED_DEFAULT_RERANKER: &str = "BAAI/bge-reranker-base";
+
+/// Where fastembed models are downloaded to and loaded from: `FASTEMBED_CACHE_PATH`,
+/// else `HF_HUB_CACHE`, else `~/.cache/fastembed`. Read once when a configuration is
+/// built and handed to fastembed, so loading never touches the environment.
+fn default_cache_dir() -> PathBuf {
+    let cache_dir = std::env::var("FASTEMBED_CACHE_PATH")
+        .or_else(|_| std::env::var("HF_HUB_CACHE"))
+        .unwrap_or_else(|_| {
+            let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+            format!("{}/.cache/fastembed", home)
+        });
+    PathBuf::from(cache_dir)
+}
+
+/// Tokenizer files a local model directory must contain next to its ONNX file.
+const TOKENIZER_FILES: [&str; 4] = [
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+
+/// Which fastembed model to load: a built-in one (downloaded to the fastembed cache on
+/// first use) or an ONNX export in a local directory, loaded without network access.
+#[derive(Debug, Clone)]
+pub struct FastEmbedConfig {
+    pub model: EmbeddingModel,
+    /// Directory with `model.onnx` (or `onnx/model.onnx`) and the tokenizer files;
+    /// replaces `model` when set.
+    pub model_dir: Option<PathBuf>,
+    /// Model id recorded for a `model_dir` model (default: the directory name).
+    pub model_name: Option<String>,
+    /// Pooling of a `model_dir` model; `None` uses the model's output as is.
+    pub pooling: Option<Pooling>,
+    pub max_length: Option<usize>,
+    /// Model instances, each on its own thread, that embed in parallel.
+    pub workers: usize,
+    /// Embedding jobs waiting for a worker before callers have to wait.
+    pub queue: usize,
+    /// Texts per job; larger calls are split so several workers can share them.
+    pub batch_size: usize,
+    /// How long a caller waits for room in a full queue before giving up.
+    pub queue_timeout: Duration,
+    /// Download cache for built-in models.
+    pub cache_dir: PathBuf,
+}
+
+impl Default for FastEmbedConfig {
+    fn default() -> Self {
+        Self {
+            model: EmbeddingModel::default(),
+            model_dir: None,
+            model_name: None,
+            pooling: Some(Pooling::Mean),
+            max_length: None,
+            workers: 1,
+            queue: 16,
+            batch_size: 64,
+            queue_timeout: Duration::from_secs(60),
+            cache_dir: default_cache_dir(),
+        }
+    }
+}
+
+impl FastEmbedConfig {
+    pub fn new(model: EmbeddingModel) -> Self {
+        Self {
+            model,
+            ..Self::default()
+        }
+    }
+
+    /// Load the ONNX model and tokenizer in `dir` instead of a built-in model.
+    pub fn local(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            model_dir: Some(dir.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_model_name(mut self, name: impl Into<String>) -> Self {
+        self.model_name = Some(name.into());
+        self
+    }
+
+    pub fn with_pooling(mut self, pooling: Option<Pooling>) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn with_queue(mut self, queue: usize, timeout: Duration) -> Self {
+        self.queue = queue.max(1);
+        self.queue_timeout = timeout;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = dir.into();
+        self
+    }
+
+    /// Model id vectors from this configuration are recorded under, known before the
+    /// model is loaded.
+    pub fn model_id(&self) -> Result<String> {
+        model_id(self)
+    }
+
+    /// `FASTEMBED_MODEL_DIR` selects a local model, named by `FASTEMBED_MODEL` and
+    /// pooled per `FASTEMBED_POOLING` (mean, cls or none); otherwise `FASTEMBED_MODEL`
+    /// names a built-in model. `FASTEMBED_MAX_LENGTH` caps tokens per text;
+    /// `FASTEMBED_WORKERS`, `FASTEMBED_QUEUE`, `FASTEMBED_BATCH_SIZE` and
+    /// `FASTEMBED_QUEUE_TIMEOUT_SECS` size the worker pool.
+    pub fn from_env() -> Result<Self> {
+        let mut config = match env_var("FASTEMBED_MODEL_DIR") {
+            Some(dir) => {
+                let mut config = Self::local(dir);
+                if let Some(name) = env_var("FASTEMBED_MODEL") {
+                    config = config.with_model_name(name.trim());
+                }
+                if let Some(pooling) = env_var("FASTEMBED_POOLING") {
+                    config = config.with_pooling(parse_pooling(&pooling)?);
+                }
+                config
+            }
+            None => match env_var("FASTEMBED_MODEL") {
+                Some(name) => Self::new(parse_model(&name)?),
+                None => Self::default(),
+            },
+        };
+        if let Some(max_length) = env_var("FASTEMBED_MAX_LENGTH") {
+            config = config.with_max_length(parse_positive("FASTEMBED_MAX_LENGTH", &max_length)?);
+        }
+        if let Some(workers) = env_var("FASTEMBED_WORKERS") {
+            config = config.with_workers(parse_positive("FASTEMBED_WORKERS", &workers)?);
+        }
+        if let Some(queue) = env_var("FASTEMBED_QUEUE") {
+            config.queue = parse_positive("FASTEMBED_QUEUE", &queue)?;
+        }
+        if let Some(timeout) = env_var("FASTEMBED_QUEUE_TIMEOUT_SECS") {
+            config.queue_timeout = Duration::from_secs(parse_positive(
+                "FASTEMBED_QUEUE_TIMEOUT_SECS",
+                &timeout,
+            )? as u64);
+        }
+        if let Some(size) = env_var("FASTEMBED_BATCH_SIZE") {
+            config = config.with_batch_size(parse_positive("FASTEMBED_BATCH_SIZE", &size)?);
+        }
+        Ok(config)
+    }
+}
+
+/// A built-in model by its model code (`intfloat/multilingual-e5-base`) or, when that
+/// is unambiguous, by the part after the slash (`multilingual-e5-base`).
+fn parse_model(name: &str) -> Result<EmbeddingModel> {
+    let name = name.trim();
+    if let Ok(model) = name.parse::<EmbeddingModel>() {
+        return Ok(model);
+    }
+    let models = TextEmbedding::list_supported_models();
+    let mut matches = models
+        .iter()
+        .filter(|m| {
+            m.model_code
+                .rsplit('/')
+                .next()
+                .is_some_and(|short| short.eq_ignore_ascii_case(name))
+        })
+        .map(|m| &m.model_code)
+        .collect::<Vec<_>>();
+    matches.dedup();
+    match matches.as_slice() {
+        [code] => Ok(code.parse().map_err(|e: String| anyhow!(e))?),
+        [] => {
+            let mut known = models
+                .iter()
+                .map(|m| m.model_code.as_str())
+                .collect::<Vec<_>>();
+            known.dedup();
+            Err(anyhow!(
+                "Unknown fastembed model '{}': use one of {}, or FASTEMBED_MODEL_DIR for a local model",
+                name,
+                known.join(", ")
+            ))
+        }
+        _ => Err(anyhow!(
+            "Ambiguous fastembed model '{}': use one of {}",
+            name,
+            matches
+                .iter()
+                .map(|c| c.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn parse_pooling(value: &str) -> Result<Option<Pooling>> {
+    match value.trim().to_lowercase().as_str() {
+        "mean" => Ok(Some(Pooling::Mean)),
+        "cls" => Ok(Some(Pooling::Cls)),
+        "none" => Ok(None),
+        other => Err(anyhow!(
+            "Unknown FASTEMBED_POOLING '{}': use mean, cls or none",
+            other
+        )),
+    }
+}
+
+/// Read a user-supplied model from `dir` without touching the network.
+fn load_local_model(dir: &Path) -> Result<UserDefinedEmbeddingModel> {
+    let onnx = ["model.onnx", "onnx/model.onnx"]
+        .iter()
+        .map(|f| dir.join(f))
+        .find(|p| p.is_file())
+        .ok_or_else(|| anyhow!("No model.onnx or onnx/model.onnx in {}", dir.display()))?;
+    let [tokenizer, config, special_tokens, tokenizer_config] = TOKENIZER_FILES.map(|f| {
+        let path = dir.join(f);
+        fs::read(&path).map_err(|e| anyhow!("Cannot read {}: {}", path.display(), e))
+    });
+    let tokenizer_files = TokenizerFiles {
+        tokenizer_file: tokenizer?,
+        config_file: config?,
+        special_tokens_map_file: special_tokens?,
+        tokenizer_config_file: tokenizer_config?,
+    };
+    Ok(UserDefinedEmbeddingModel::new(
+        fs::read(&onnx)?,
+        tokenizer_files,
+    ))
+}
+
+/// Texts to embed and where to send the vectors.
+type Job = (Vec<String>, oneshot::Sender<Result<Vec<Vec<f32>>>>);
+
+/// Runs fastembed on dedicated threads so ONNX inference never blocks the async
+/// runtime. Calls are split into jobs on a bounded queue; when it is full, callers
+/// wait (without blocking a runtime thread) for up to the queue timeout.
+pub struct FastEmbedder {
+    jobs: mpsc::Sender<Job>,
+    model_id: String,
+    token_limit: TokenLimit,
+    batching: Batching,
+    queue_timeout: Duration,
+}
+
+impl FastEmbedder {
+    /// The model configured by [`FastEmbedConfig::from_env`].
+    pub fn new() -> Result<Self> {
+        Self::with_config(FastEmbedConfig::from_env()?)
+    }
+
+    /// Load `config.workers` instances of the model, each on its own thread.
+    pub fn with_config(config: FastEmbedConfig) -> Result<Self> {
+        let model_id = model_id(&config)?;
+        let loader = config.clone();
+        Self::spawn_workers(model_id, &config, move || {
+            let mut model = load_model(&loader)?;
+            let token_limit = TokenLimit::exact(model.tokenizer.clone());
+            Ok((
+                move |texts: Vec<String>| model.embed(texts, None),
+                token_limit,
+            ))
+        })
+    }
+
+    /// Start `config.workers` threads that each build an inference function with `load`
+    /// and run queued jobs through it.
+    fn spawn_workers<L, I>(model_id: String, config: &FastEmbedConfig, load: L) -> Result<Self>
+    where
+        L: Fn() -> Result<(I, TokenLimit)> + Clone + Send + 'static,
+        I: FnMut(Vec<String>) -> Result<Vec<Vec<f32>>>,
+    {
+        let (jobs, queue) = mpsc::channel::<Job>(config.queue);
+        let queue = Arc::new(StdMutex::new(queue));
+        let (ready_tx, ready) = std::sync::mpsc::channel();
+        for worker in 0..config.workers {
+            let (load, queue, ready) = (load.clone(), queue.clone(), ready_tx.clone());
+            std::thread::Builder::new()
+                .name(format!("fastembed-{}", worker))
+                .spawn(move || {
+                    let mut infer = match load() {
+                        Ok((infer, token_limit)) => {
+                            let _ = ready.send(Ok(token_limit));
+                            infer
+                        }
+                        Err(e) => {
+                            let _ = ready.send(Err(e));
+                            return;
+                        }
+                    };
+                    drop(ready);
+                    loop {
+                        // Idle workers queue up on the lock; the holder takes the next job.
+                        let job = queue
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .blocking_recv();
+                        let Some((texts, reply)) = job else {
+                            return;
+                        };
+                        let _ = reply.send(infer(texts));
+                    }
+                })?;
+        }
+        drop(ready_tx);
+        let mut token_limit = TokenLimit::unknown();
+        for _ in 0..config.workers {
+            token_limit = ready.recv()??;
+        }
+        tracing::info!(
+            "Loaded fastembed model {} on {} worker(s)",
+            model_id,
+            config.workers
+        );
+        Ok(Self {
+            jobs,
+            model_id,
+            token_limit,
+            batching: Batching {
+                size: config.batch_size,
+                concurrency: config.workers,
+            },
+            queue_timeout: config.queue_timeout,
+        })
+    }
+
+    /// Queue one job and wait for its vectors.
+    async fn embed_job(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let (reply, vectors) = oneshot::channel();
+        let job = (texts.to_vec(), reply);
+        let job = match self.jobs.try_send(job) {
+            Ok(()) => None,
+            Err(TrySendError::Full(job)) => Some(job),
+            Err(TrySendError::Closed(_)) => return Err(anyhow!("fastembed workers have stopped")),
+        };
+        if let Some(job) = job {
+            tracing::debug!("fastembed queue is full; waiting for a free slot");
+            self.jobs
+                .send_timeout(job, self.queue_timeout)
+                .await
+                .map_err(|e| match e {
+                    SendTimeoutError::Timeout(_) => anyhow!(
+                        "fastembed queue stayed full for {}s; try again later",
+                        self.queue_timeout.as_secs()
+                    ),
+                    SendTimeoutError::Closed(_) => anyhow!("fastembed workers have stopped"),
+                })?;
+        }
+        vectors
+            .await
+            .map_err(|_| anyhow!("fastembed worker stopped before answering"))?
+    }
+}
+
+/// Id recorded with collections for the configured model.
+fn model_id(config: &FastEmbedConfig) -> Result<String> {
+    match &config.model_dir {
+        Some(dir) => match &config.model_name {
+            Some(name) => Ok(name.clone()),
+            None => dir
+                .canonicalize()?
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .ok_or_else(|| anyhow!("Cannot name the model in {}", dir.display())),
+        },
+        None => Ok(TextEmbedding::get_model_info(&config.model)?
+            .model_code
+            .clone()),
+    }
+}
+
+fn load_model(config: &FastEmbedConfig) -> Result<TextEmbedding> {
+    if let Some(dir) = &config.model_dir {
+        let mut model = load_local_model(dir)?;
+        if let Some(pooling) = config.pooling.clone() {
+            model = model.with_pooling(pooling);
+        }
+        let mut options = InitOptionsUserDefined::new();
+        if let Some(max_length) = config.max_length {
+            options = options.with_max_length(max_length);
+        }
+        return TextEmbedding::try_new_from_user_defined(model, options);
+    }
+
+    fs::create_dir_all(&config.cache_dir)?;
+    let mut options =
+        TextInitOptions::new(config.model.clone()).with_cache_dir(config.cache_dir.clone());
+    if let Some(max_length) = config.max_length {
+        options = options.with_max_length(max_length);
+    }
+    TextEmbedding::try_new(options)
+}
+
+#[async_trait]
+impl Embedder for FastEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn provider(&self) -> &str {
+        "fastembed"
+    }
+
+    fn token_limit(&self) -> TokenLimit {
+        self.token_limit.clone()
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_in_batches(texts, self.batching, |batch| self.embed_job(batch)).await
+    }
+}
+
+/// Local cross-encoder reranking with a fastembed `TextRerank` model. The model is
+/// loaded (and downloaded into the fastembed cache) on the first rerank, so servers
+/// whose primary reranker never fails do not pay for it.
+pub struct FastReranker {
+    model: RerankerModel,
+    model_id: String,
+    cache_dir: PathBuf,
+    loaded: OnceCell<Arc<StdMutex<TextRerank>>>,
+}
+
+impl FastReranker {
+    pub fn new(model: RerankerModel) -> Self {
+        Self {
+            model_id: model.to_string(),
+            model,
+            cache_dir: default_cache_dir(),
+            loaded: OnceCell::new(),
+        }
+    }
+
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = dir.into();
+        self
+    }
+
+    /// Look up a supported reranker by its model code, e.g. `BAAI/bge-reranker-base`
+    /// or `jinaai/jina-reranker-v2-base-multilingual`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        let model = name.trim().parse::<RerankerModel>().map_err(|_| {
+            let known = TextRerank::list_supported_models()
+                .into_iter()
+                .map(|m| m.model_code)
+                .collect::<Vec<_>>();
+            anyhow!(
+                "Unknown fastembed reranker '{}': use one of {}",
+                name,
+                known.join(", ")
+            )
+        })?;
+        Ok(Self::new(model))
+    }
+
+    /// The reranker named by `FASTEMBED_RERANKER_MODEL` (default
+    /// `BAAI/bge-reranker-base`), or `None` when it is set to `none`.
+    pub fn from_env() -> Result<Option<Self>> {
+        match env_var("FASTEMBED_RERANKER_MODEL") {
+            Some(name) if name.trim().eq_ignore_ascii_case("none") => Ok(None),
+            Some(name) => Self::from_name(&name).map(Some),
+            None => Self::from_name(FASTEMBED_DEFAULT_RERANKER).map(Some),
+        }
+    }
+
+    async fn model(&self) -> Result<Arc<StdMutex<TextRerank>>> {
+        let model = self
+            .loaded
+            .get_or_try_init(|| async {
+                fs::create_dir_all(&self.cache_dir)?;
+                let options = RerankInitOptions::new(self.model.clone())
+                    .with_cache_dir(self.cache_dir.clone());
+                tracing::info!("Loading fastembed reranker {}", self.model_id);
+                let model =
+                    tokio::task::spawn_blocking(move || TextRerank::try_new(options)).await??;
+                Ok::<_, anyhow::Error>(Arc::new(StdMutex::new(model)))
+            })
+            .await?;
+        Ok(model.clone())
+    }
+}
+
+#[async_trait]
+impl Reranker for FastReranker {
+    fn reranker_model(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
+        let model = self.model().await?;
+        let query = query.to_string();
+        let documents = documents.to_vec();
+        let results = tokio::task::spawn_blocking(move || {
+            let mut model = model.lock().unwrap_or_else(|e| e.into_inner());
+            model.rerank(
+                query.as_str(),
+                documents.iter().map(String::as_str).collect(),
+                false,
+                None,
+            )
+        })
+        .await??;
+        Ok(results.into_iter().map(|r| (r.index, r.score)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    fn pool(workers: usize, queue: usize, batch_size: usize) -> FastEmbedConfig {
+        FastEmbedConfig::default()
+            .with_workers(workers)
+            .with_queue(queue, Duration::from_millis(50))
+            .with_batch_size(batch_size)
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_fails_after_the_timeout() {
+        let (started_tx, mut started) = tokio::sync::mpsc::unbounded_channel();
+        let (release, gate) = std::sync::mpsc::channel::<()>();
+        let gate = Arc::new(StdMutex::new(gate));
+        let embedder = FastEmbedder::spawn_workers("stub".into(), &pool(1, 1, 1), move || {
+            let (started, gate) = (started_tx.clone(), gate.clone());
+            let infer = move |texts: Vec<String>| {
+                let _ = started.send(());
+                let _ = gate.lock().unwrap().recv();
+                Ok(texts.iter().map(|_| vec![1.0]).collect())
+            };
+            Ok((infer, TokenLimit::unknown()))
+        })
+        .unwrap();
+        let embedder = Arc::new(embedder);
+        let texts = vec!["a".to_string()];
+
+        // One job blocks the only worker and a second fills the queue.
+        let first = tokio::spawn({
+            let (embedder, texts) = (embedder.clone(), texts.clone());
+            async move { embedder.embed_batch(&texts).await }
+        });
+        started.recv().await.unwrap();
+        let second = tokio::spawn({
+            let (embedder, texts) = (embedder.clone(), texts.clone());
+            async move { embedder.embed_batch(&texts).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let waited = Instant::now();
+        let err = embedder.embed_batch(&texts).await.unwrap_err();
+        assert!(err.to_string().contains("queue stayed full"));
+        assert!(waited.elapsed() >= Duration::from_millis(50));
+        assert!(waited.elapsed() < Duration::from_secs(5));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        assert_eq!(first.await.unwrap().unwrap(), vec![vec![1.0]]);
+        assert_eq!(second.await.unwrap().unwrap(), vec![vec![1.0]]);
+    }
+
+    #[tokio::test]
+    async fn results_keep_input_order_across_workers() {
+        let threads = Arc::new(StdMutex::new(HashSet::new()));
+        let seen = threads.clone();
+        let embedder = FastEmbedder::spawn_workers("stub".into(), &pool(3, 8, 2), move || {
+            let seen = seen.clone();
+            let infer = move |texts: Vec<String>| {
+                let name = std::thread::current().name().unwrap_or("").to_string();
+                seen.lock().unwrap().insert(name);
+                // Earlier jobs finish last.
+                let first: u64 = texts[0].parse()?;
+                std::thread::sleep(Duration::from_millis(30 - first * 3));
+                Ok(texts.iter().map(|t| vec![t.parse().unwrap()]).collect())
+            };
+            Ok((infer, TokenLimit::unknown()))
+        })
+        .unwrap();
+
+        let texts = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let vectors = embedder.embed_batch(&texts).await.unwrap();
+        let flat = vectors.iter().map(|v| v[0] as usize).collect::<Vec<_>>();
+        assert_eq!(flat, (0..10).collect::<Vec<_>>());
+        assert!(threads.lock().unwrap().len() > 1);
+    }
+
+    #[test]
+    fn load_failures_are_reported() {
+        let result = FastEmbedder::spawn_workers("stub".into(), &pool(2, 1, 1), || {
+            Err::<(fn(Vec<String>) -> Result<Vec<Vec<f32>>>, TokenLimit), _>(anyhow!("no model"))
+        });
+        assert!(result.err().unwrap().to_string().contains("no model"));
+    }
+
+    #[test]
+    fn model_names_resolve_by_code_or_short_name() {
+        assert_eq!(
+            parse_model("intfloat/multilingual-e5-base").unwrap(),
+            EmbeddingModel::MultilingualE5Base
+        );
+        assert_eq!(
+            parse_model("multilingual-e5-small").unwrap(),
+            EmbeddingModel::MultilingualE5Small
+        );
+        assert!(parse_model("bge-m3").is_err());
+        assert!(parse_pooling("CLS").unwrap().is_some());
+        assert!(parse_pooling("max").is_err());
+    }
+
+    #[test]
+    fn local_models_need_onnx_and_tokenizer_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("onnx")).unwrap();
+        let err = load_local_model(dir).unwrap_err();
+        assert!(err.to_string().contains("model.onnx"));
+
+        fs::write(dir.join("onnx/model.onnx"), b"onnx").unwrap();
+        for file in &TOKENIZER_FILES[..3] {
+            fs::write(dir.join(file), b"{}").unwrap();
+        }
+        let err = load_local_model(dir).unwrap_err();
+        assert!(err.to_string().contains("tokenizer_config.json"));
+
+        fs::write(dir.join("tokenizer_config.json"), b"{}").unwrap();
+        let model = load_local_model(dir).unwrap();
+        assert_eq!(model.onnx_file, b"onnx");
+    }
+
+    #[test]
+    fn reranker_names_resolve_to_fastembed_models() {
+        let reranker = FastReranker::from_name("jinaai/JINA-reranker-v1-turbo-en").unwrap();
+        assert_eq!(
+            reranker.reranker_model(),
+            "jinaai/jina-reranker-v1-turbo-en"
+        );
+        let err = FastReranker::from_name("bge-tiny").err().unwrap();
+        assert!(err.to_string().contains(FASTEMBED_DEFAULT_RERANKER));
+    }
+}
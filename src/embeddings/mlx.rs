@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{embed_in_batches, get_ok, post_json, Batching, Embedder, Reranker};
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    input: &'a [String],
+    model: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: Option<usize>,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct RerankRequest {
+    query: String,
+    documents: Vec<String>,
+    model: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResult {
+    index: usize,
+    score: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelInfo {
+    id: String,
+}
+
+pub struct MLXBridge {
+    client: Client,
+    embedder_url: String,
+    reranker_url: String,
+    models_url: String,
+    reranker_models_url: String,
+    embedder_model: String,
+    reranker_model: String,
+    batching: Batching,
+}
+
+impl MLXBridge {
+    pub async fn new() -> Result<Self> {
+        if std::env::var("DISABLE_MLX").is_ok() {
+            return Err(anyhow!("MLX bridge disabled via DISABLE_MLX"));
+        }
+
+        // Base URL for the MLX HTTP bridge. Default to localhost so it's meaningful for most users.
+        let dragon_base =
+            std::env::var("DRAGON_BASE_URL").unwrap_or_else(|_| "http://localhost".to_string());
+
+        // Check if JIT mode (single port for all models)
+        let jit_mode =
+            std::env::var("MLX_JIT_MODE").unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let (embedder_port, reranker_port) = if jit_mode {
+            // JIT mode - same port for both
+            let port = std::env::var("MLX_JIT_PORT").unwrap_or_else(|_| "1234".to_string());
+            (port.clone(), port)
+        } else {
+            // Separate ports mode
+            let embedder_port =
+                std::env::var("EMBEDDER_PORT").unwrap_or_else(|_| "12345".to_string());
+            let reranker_port =
+                std::env::var("RERANKER_PORT").unwrap_or_else(|_| "12346".to_string());
+            (embedder_port, reranker_port)
+        };
+
+        let embedder_model = std::env::var("EMBEDDER_MODEL")
+            .unwrap_or_else(|_| "Qwen/Qwen3-Embedding-4B".to_string());
+        let reranker_model = std::env::var("RERANKER_MODEL")
+            .unwrap_or_else(|_| "Qwen/Qwen3-Reranker-4B".to_string());
+
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()?;
+
+        let bridge = Self {
+            client,
+            embedder_url: format!("{}:{}/v1/embeddings", dragon_base, embedder_port),
+            reranker_url: format!("{}:{}/v1/rerank", dragon_base, reranker_port),
+            models_url: format!("{}:{}/v1/models", dragon_base, embedder_port),
+            reranker_models_url: format!("{}:{}/v1/models", dragon_base, reranker_port),
+            embedder_model,
+            reranker_model,
+            batching: Batching::from_env()?,
+        };
+
+        // List available models
+        if let Ok(models) = bridge.list_models(&dragon_base, &embedder_port).await {
+            tracing::info!(
+                "Available models on embedder port {}: {:?}",
+                embedder_port,
+                models
+            );
+        }
+
+        if !jit_mode {
+            if let Ok(models) = bridge.list_models(&dragon_base, &reranker_port).await {
+                tracing::info!(
+                    "Available models on reranker port {}: {:?}",
+                    reranker_port,
+                    models
+                );
+            }
+        }
+
+        Ok(bridge)
+    }
+
+    pub fn with_batching(mut self, batching: Batching) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// One `/v1/embeddings` request for all of `texts`.
+    async fn embed_request(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = EmbeddingRequest {
+            input: texts,
+            model: &self.embedder_model,
+        };
+
+        let response: EmbeddingResponse = post_json(
+            self.client.post(&self.embedder_url).json(&request),
+            &self.embedder_url,
+        )
+        .await?;
+
+        let mut data = response.data;
+        if data.iter().all(|d| d.index.is_some()) {
+            data.sort_by_key(|d| d.index);
+        }
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    async fn list_models(&self, base_url: &str, port: &str) -> Result<Vec<String>> {
+        let url = format!("{}:{}/v1/models", base_url, port);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await?
+            .json::<ModelsResponse>()
+            .await?;
+
+        Ok(response.data.into_iter().map(|m| m.id).collect())
+    }
+}
+
+#[async_trait]
+impl Embedder for MLXBridge {
+    fn model_id(&self) -> &str {
+        &self.embedder_model
+    }
+
+    fn provider(&self) -> &str {
+        "mlx"
+    }
+
+    async fn health(&self) -> Result<()> {
+        get_ok(self.client.get(&self.models_url), &self.models_url).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_in_batches(texts, self.batching, |batch| self.embed_request(batch)).await
+    }
+}
+
+#[async_trait]
+impl Reranker for MLXBridge {
+    fn reranker_model(&self) -> &str {
+        &self.reranker_model
+    }
+
+    async fn health(&self) -> Result<()> {
+        get_ok(
+            self.client.get(&self.reranker_models_url),
+            &self.reranker_models_url,
+        )
+        .await
+    }
+
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
+        let request = RerankRequest {
+            query: query.to_string(),
+            documents: documents.to_vec(),
+            model: self.reranker_model.clone(),
+        };
+
+        let response: RerankResponse = post_json(
+            self.client.post(&self.reranker_url).json(&request),
+            &self.reranker_url,
+        )
+        .await?;
+
+        Ok(response
+            .results
+            .into_iter()
+            .map(|r| (r.index, r.score))
+            .collect())
+    }
+}
@@ -1,219 +1,443 @@
 use anyhow::{anyhow, Result};
-use fastembed::{TextEmbedding, TextInitOptions};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::fs;
-use tokio::sync::Mutex;
-
-#[derive(Debug, Serialize)]
-struct EmbeddingRequest {
-    input: Vec<String>,
-    model: String,
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod fast;
+mod mlx;
+mod ollama;
+mod openai;
+mod prompts;
+mod resilient;
+mod tokens;
+
+pub use fast::{FastEmbedConfig, FastEmbedder, FastReranker, FASTEMBED_DEFAULT_RERANKER};
+pub use mlx::MLXBridge;
+pub use ollama::{OllamaConfig, OllamaEmbedder, OLLAMA_DEFAULT_URL};
+pub use openai::{OpenAIConfig, OpenAIEmbedder, OPENAI_EMBEDDINGS_PATH};
+pub use prompts::{EmbedRole, PromptTemplate, Prompts};
+pub use resilient::{CircuitState, CircuitStatus, Resilience, ResilientEmbedder};
+pub use tokens::{Chunking, TokenLimit};
+
+/// Request timeout of the HTTP embedding providers.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the HTTP providers split large inputs: at most `size` texts per request and at
+/// most `concurrency` requests in flight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batching {
+    pub size: usize,
+    pub concurrency: usize,
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingResponse {
-    data: Vec<EmbeddingData>,
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            size: 32,
+            concurrency: 4,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct EmbeddingData {
-    embedding: Vec<f32>,
+impl Batching {
+    /// Defaults overridden by `EMBED_BATCH_SIZE` and `EMBED_CONCURRENCY`.
+    pub fn from_env() -> Result<Self> {
+        let mut batching = Self::default();
+        if let Some(size) = env_var("EMBED_BATCH_SIZE") {
+            batching.size = parse_positive("EMBED_BATCH_SIZE", &size)?;
+        }
+        if let Some(concurrency) = env_var("EMBED_CONCURRENCY") {
+            batching.concurrency = parse_positive("EMBED_CONCURRENCY", &concurrency)?;
+        }
+        Ok(batching)
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct RerankRequest {
-    query: String,
-    documents: Vec<String>,
-    model: String,
+fn parse_positive(name: &str, value: &str) -> Result<usize> {
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(anyhow!("{} must be a positive integer", name)),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct RerankResponse {
-    results: Vec<RerankResult>,
+/// Embed `texts` as requests of `batching.size` texts, running up to
+/// `batching.concurrency` at once, and return the vectors in input order.
+async fn embed_in_batches<'a, F, Fut>(
+    texts: &'a [String],
+    batching: Batching,
+    embed: F,
+) -> Result<Vec<Vec<f32>>>
+where
+    F: Fn(&'a [String]) -> Fut,
+    Fut: Future<Output = Result<Vec<Vec<f32>>>>,
+{
+    // Requests are created up front; `buffered` starts them lazily and yields in order.
+    let requests = texts
+        .chunks(batching.size.max(1))
+        .map(|batch| {
+            let request = embed(batch);
+            async move {
+                let vectors = request.await?;
+                if vectors.len() != batch.len() {
+                    return Err(anyhow!(
+                        "Embedding request returned {} vectors for {} texts",
+                        vectors.len(),
+                        batch.len()
+                    ));
+                }
+                Ok(vectors)
+            }
+        })
+        .collect::<Vec<_>>();
+    let batches = futures::stream::iter(requests)
+        .buffered(batching.concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(batches.into_iter().flatten().collect())
 }
 
-#[derive(Debug, Deserialize)]
-struct RerankResult {
-    index: usize,
-    score: f32,
-}
+/// Turns text into vectors for the pipeline.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Identifier of the model, recorded with every collection it writes to.
+    fn model_id(&self) -> &str;
 
-#[derive(Debug, Deserialize)]
-struct ModelsResponse {
-    data: Vec<ModelInfo>,
-}
+    /// Short provider name shown by `embedder_status`.
+    fn provider(&self) -> &str {
+        "custom"
+    }
 
-#[derive(Debug, Deserialize)]
-struct ModelInfo {
-    id: String,
-}
+    /// Cheap reachability check used to bring a failed provider back.
+    async fn health(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// How many tokens the model reads per text; texts beyond it are truncated.
+    fn token_limit(&self) -> TokenLimit {
+        TokenLimit::unknown()
+    }
+
+    /// One vector per text, in input order.
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Vectors together with the model that produced them. Providers that can switch
+    /// models between calls override this so vectors are never recorded under the wrong
+    /// model.
+    async fn embed_with_model(&self, texts: &[String]) -> Result<(String, Vec<Vec<f32>>)> {
+        let model = self.model_id().to_string();
+        Ok((model, self.embed_batch(texts).await?))
+    }
 
-pub struct MLXBridge {
-    client: Client,
-    embedder_url: String,
-    reranker_url: String,
-    embedder_model: String,
-    reranker_model: String,
+    fn status(&self) -> EmbedderStatus {
+        EmbedderStatus {
+            provider: self.provider().to_string(),
+            model: self.model_id().to_string(),
+            circuit: None,
+            reranker: None,
+            reranker_circuit: None,
+        }
+    }
 }
 
-pub struct FastEmbedder {
-    model: Mutex<TextEmbedding>,
+/// Which provider and model currently embed, as reported by `embedder_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbedderStatus {
+    pub provider: String,
+    pub model: String,
+    /// Present when the provider is wrapped in a [`ResilientEmbedder`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit: Option<CircuitStatus>,
+    /// Model search results are reranked with; `None` means cosine order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reranker: Option<String>,
+    /// Present when reranking goes through a [`ResilientEmbedder`]'s primary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reranker_circuit: Option<CircuitStatus>,
 }
 
-impl FastEmbedder {
-    pub fn new() -> Result<Self> {
-        // Default fastembed cache to ~/.cache/fastembed unless user overrides via env.
-        let cache_dir = std::env::var("FASTEMBED_CACHE_PATH")
-            .or_else(|_| std::env::var("HF_HUB_CACHE"))
-            .unwrap_or_else(|_| {
-                let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-                format!("{}/.cache/fastembed", home)
-            });
+/// Reorders search candidates by relevance to the query.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Reranking model shown by `embedder_status`.
+    fn reranker_model(&self) -> &str {
+        "custom"
+    }
 
-        if std::env::var("FASTEMBED_CACHE_PATH").is_err() {
-            std::env::set_var("FASTEMBED_CACHE_PATH", &cache_dir);
+    /// Cheap reachability check used to bring a failed reranker back.
+    async fn health(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// `(index into documents, score)` pairs, best first.
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>>;
+}
+
+/// An embedder and the reranker to use with it, if any.
+pub type Providers = (Arc<dyn Embedder>, Option<Arc<dyn Reranker>>);
+
+/// Providers chosen by `EMBEDDING_PROVIDER`: `mlx` (default) uses the MLX bridge for
+/// embeddings and reranking, embedding with fastembed while the bridge is down only if
+/// fastembed is configured with the same model (and with fastembed alone when the
+/// bridge is disabled via `DISABLE_MLX`); `openai`, `ollama` and `fastembed` embed with that
+/// provider only. Remote providers are retried and guarded by a circuit breaker
+/// configured by [`Resilience::from_env`]. Unless `FASTEMBED_RERANKER_MODEL=none`, a
+/// local fastembed reranker reranks whenever the MLX reranker cannot.
+pub async fn default_providers() -> Result<Providers> {
+    let provider = env_var("EMBEDDING_PROVIDER").unwrap_or_else(|| "mlx".to_string());
+    let local_reranker = FastReranker::from_env()?.map(Arc::new);
+    let remote: Arc<dyn Embedder> = match provider.trim().to_lowercase().as_str() {
+        "mlx" => {
+            let mlx = match MLXBridge::new().await {
+                Ok(mlx) => Arc::new(mlx),
+                Err(e) => {
+                    tracing::warn!("MLX bridge disabled, using fastembed only: {}", e);
+                    return local_only(local_reranker);
+                }
+            };
+            let fallback =
+                FastEmbedConfig::from_env().and_then(|c| matching_fallback(mlx.as_ref(), c));
+            let mut resilient = ResilientEmbedder::new(mlx.clone())
+                .with_reranker(mlx)
+                .with_resilience(Resilience::from_env()?);
+            match fallback {
+                Ok(Some(fast)) => resilient = resilient.with_fallback(Arc::new(fast)),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("No fastembed fallback for the MLX bridge: {}", e),
+            }
+            if let Some(reranker) = local_reranker {
+                resilient = resilient.with_fallback_reranker(reranker);
+            }
+            let resilient = Arc::new(resilient).start().await;
+            return Ok((resilient.clone(), Some(resilient)));
         }
-        if std::env::var("HF_HUB_CACHE").is_err() {
-            std::env::set_var("HF_HUB_CACHE", &cache_dir);
+        "openai" => Arc::new(OpenAIEmbedder::new(OpenAIConfig::from_env()?)?),
+        "ollama" => Arc::new(OllamaEmbedder::new(OllamaConfig::from_env()?)?),
+        "fastembed" => return local_only(local_reranker),
+        other => {
+            return Err(anyhow!(
+                "Unknown EMBEDDING_PROVIDER '{}': use mlx, openai, ollama or fastembed",
+                other
+            ))
         }
-        fs::create_dir_all(&cache_dir)?;
+    };
+    let resilient = ResilientEmbedder::new(remote).with_resilience(Resilience::from_env()?);
+    Ok((
+        Arc::new(resilient).start().await,
+        local_reranker.map(|r| r as Arc<dyn Reranker>),
+    ))
+}
 
-        let model = TextEmbedding::try_new(TextInitOptions::default())?;
-        Ok(Self {
-            model: Mutex::new(model),
-        })
+/// A fastembed stand-in for `primary`, if `config` loads the same model. Vectors of any
+/// other model could not be written to or searched in the primary's collections.
+fn matching_fallback(
+    primary: &dyn Embedder,
+    config: FastEmbedConfig,
+) -> Result<Option<FastEmbedder>> {
+    let model = config.model_id()?;
+    if model != primary.model_id() {
+        tracing::info!(
+            "No fastembed fallback for {}: it would embed with '{}' instead of '{}'",
+            primary.provider(),
+            model,
+            primary.model_id()
+        );
+        return Ok(None);
     }
+    Ok(Some(FastEmbedder::with_config(config)?))
+}
 
-    pub async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
-        let mut model = self.model.lock().await;
-        let embeddings = model.embed(texts.to_vec(), None)?;
-        Ok(embeddings)
-    }
+fn local_only(reranker: Option<Arc<FastReranker>>) -> Result<Providers> {
+    Ok((
+        Arc::new(FastEmbedder::new()?),
+        reranker.map(|r| r as Arc<dyn Reranker>),
+    ))
 }
 
-impl MLXBridge {
-    pub async fn new() -> Result<Self> {
-        if std::env::var("DISABLE_MLX").is_ok() {
-            return Err(anyhow!("MLX bridge disabled via DISABLE_MLX"));
-        }
+/// Non-empty value of an environment variable.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
 
-        // Base URL for the MLX HTTP bridge. Default to localhost so it's meaningful for most users.
-        let dragon_base =
-            std::env::var("DRAGON_BASE_URL").unwrap_or_else(|_| "http://localhost".to_string());
-
-        // Check if JIT mode (single port for all models)
-        let jit_mode =
-            std::env::var("MLX_JIT_MODE").unwrap_or_else(|_| "false".to_string()) == "true";
-
-        let (embedder_port, reranker_port) = if jit_mode {
-            // JIT mode - same port for both
-            let port = std::env::var("MLX_JIT_PORT").unwrap_or_else(|_| "1234".to_string());
-            (port.clone(), port)
-        } else {
-            // Separate ports mode
-            let embedder_port =
-                std::env::var("EMBEDDER_PORT").unwrap_or_else(|_| "12345".to_string());
-            let reranker_port =
-                std::env::var("RERANKER_PORT").unwrap_or_else(|_| "12346".to_string());
-            (embedder_port, reranker_port)
-        };
+/// Send a request and decode its JSON answer; error statuses become errors carrying
+/// the response body.
+async fn post_json<T: DeserializeOwned>(request: RequestBuilder, url: &str) -> Result<T> {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("{} answered {}: {}", url, status, body.trim()));
+    }
+    Ok(response.json().await?)
+}
 
-        let embedder_model = std::env::var("EMBEDDER_MODEL")
-            .unwrap_or_else(|_| "Qwen/Qwen3-Embedding-4B".to_string());
-        let reranker_model = std::env::var("RERANKER_MODEL")
-            .unwrap_or_else(|_| "Qwen/Qwen3-Reranker-4B".to_string());
-
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
-
-        let bridge = Self {
-            client,
-            embedder_url: format!("{}:{}/v1/embeddings", dragon_base, embedder_port),
-            reranker_url: format!("{}:{}/v1/rerank", dragon_base, reranker_port),
-            embedder_model,
-            reranker_model,
+/// GET `url` and fail unless it answers with a success status.
+async fn get_ok(request: RequestBuilder, url: &str) -> Result<()> {
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("{} answered {}", url, status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn batches_keep_input_order_and_bound_concurrency() {
+        let texts = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let batching = Batching {
+            size: 3,
+            concurrency: 2,
         };
+        let vectors = embed_in_batches(&texts, batching, |batch| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                // Earlier batches finish last.
+                let first: u64 = batch[0].parse().unwrap();
+                tokio::time::sleep(Duration::from_millis(40 - first * 4)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(batch
+                    .iter()
+                    .map(|t| vec![t.parse::<f32>().unwrap()])
+                    .collect())
+            }
+        })
+        .await
+        .unwrap();
 
-        // List available models
-        if let Ok(models) = bridge.list_models(&dragon_base, &embedder_port).await {
-            tracing::info!(
-                "Available models on embedder port {}: {:?}",
-                embedder_port,
-                models
-            );
-        }
+        let flat = vectors.iter().map(|v| v[0] as usize).collect::<Vec<_>>();
+        assert_eq!(flat, (0..10).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
 
-        if !jit_mode {
-            if let Ok(models) = bridge.list_models(&dragon_base, &reranker_port).await {
-                tracing::info!(
-                    "Available models on reranker port {}: {:?}",
-                    reranker_port,
-                    models
-                );
-            }
-        }
+    #[test]
+    fn fallbacks_must_embed_with_the_primary_model() {
+        let primary = mock::FakeEmbedder::new("Qwen/Qwen3-Embedding-4B", 8);
+        assert!(matching_fallback(&primary, FastEmbedConfig::default())
+            .unwrap()
+            .is_none());
+
+        // A matching model is loaded; this directory has none, so loading fails.
+        let dir = tempfile::tempdir().unwrap();
+        let config = FastEmbedConfig::local(dir.path()).with_model_name("Qwen/Qwen3-Embedding-4B");
+        assert!(matching_fallback(&primary, config).is_err());
+    }
 
-        Ok(bridge)
+    #[tokio::test]
+    async fn short_answers_are_errors() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        let result = embed_in_batches(&texts, Batching::default(), |_| async {
+            Ok(vec![vec![0.0]])
+        })
+        .await;
+        assert!(result.is_err());
     }
+}
 
-    async fn list_models(&self, base_url: &str, port: &str) -> Result<Vec<String>> {
-        let url = format!("{}:{}/v1/models", base_url, port);
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<ModelsResponse>()
-            .await?;
+#[cfg(test)]
+pub(crate) mod mock {
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use super::Embedder;
+
+    /// Deterministic embedder: each text becomes a `dim`-wide histogram of its bytes, so
+    /// texts sharing characters land close together.
+    pub struct FakeEmbedder {
+        model: &'static str,
+        dim: usize,
+        /// Texts embedded so far.
+        pub embedded: AtomicUsize,
+    }
 
-        Ok(response.data.into_iter().map(|m| m.id).collect())
+    impl FakeEmbedder {
+        pub fn new(model: &'static str, dim: usize) -> Self {
+            Self {
+                model,
+                dim,
+                embedded: AtomicUsize::new(0),
+            }
+        }
     }
 
-    pub async fn embed(&mut self, text: &str) -> Result<Vec<f32>> {
-        let request = EmbeddingRequest {
-            input: vec![text.to_string()],
-            model: self.embedder_model.clone(),
-        };
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        fn model_id(&self) -> &str {
+            self.model
+        }
 
-        let response = self
-            .client
-            .post(&self.embedder_url)
-            .json(&request)
-            .send()
-            .await?
-            .json::<EmbeddingResponse>()
-            .await?;
-
-        response
-            .data
-            .into_iter()
-            .next()
-            .map(|d| d.embedding)
-            .ok_or_else(|| anyhow!("No embedding returned"))
-    }
-
-    pub async fn rerank(&mut self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
-        let request = RerankRequest {
-            query: query.to_string(),
-            documents: documents.to_vec(),
-            model: self.reranker_model.clone(),
-        };
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.embedded.fetch_add(texts.len(), Ordering::SeqCst);
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut vector = vec![0.0; self.dim];
+                    for byte in text.to_lowercase().bytes() {
+                        vector[byte as usize % self.dim] += 1.0;
+                    }
+                    vector
+                })
+                .collect())
+        }
+    }
 
-        let response = self
-            .client
-            .post(&self.reranker_url)
-            .json(&request)
-            .send()
-            .await?
-            .json::<RerankResponse>()
-            .await?;
-
-        Ok(response
-            .results
-            .into_iter()
-            .map(|r| (r.index, r.score))
-            .collect())
+    /// Answer the next HTTP request on a local port with `status` and a JSON `body`.
+    /// Returns the base URL and a handle yielding the raw request (head and body).
+    pub async fn serve_once(status: u16, body: &str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let body = body.to_string();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut raw = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                raw.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&raw).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|l| {
+                            let (name, value) = l.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if raw.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+            String::from_utf8_lossy(&raw).to_string()
+        });
+        (base, handle)
     }
 }
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{embed_in_batches, env_var, get_ok, post_json, Batching, Embedder, HTTP_TIMEOUT};
+
+/// Address of a local Ollama server.
+pub const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+/// Ollama's native `/api/embed` endpoint.
+#[derive(Debug, Clone)]
+pub struct OllamaConfig {
+    pub base_url: String,
+    pub model: String,
+    pub batching: Batching,
+}
+
+impl OllamaConfig {
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            model: model.into(),
+            batching: Batching::default(),
+        }
+    }
+
+    pub fn with_batching(mut self, batching: Batching) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// Read `OLLAMA_EMBEDDING_MODEL` (required) and `OLLAMA_HOST` (default
+    /// `http://localhost:11434`).
+    pub fn from_env() -> Result<Self> {
+        let model = env_var("OLLAMA_EMBEDDING_MODEL")
+            .ok_or_else(|| anyhow!("OLLAMA_EMBEDDING_MODEL is required for the ollama provider"))?;
+        let mut base_url = env_var("OLLAMA_HOST").unwrap_or_else(|| OLLAMA_DEFAULT_URL.to_string());
+        // Ollama itself accepts a bare host:port here.
+        if !base_url.contains("://") {
+            base_url = format!("http://{}", base_url);
+        }
+        Ok(Self::new(base_url, model).with_batching(Batching::from_env()?))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+pub struct OllamaEmbedder {
+    client: Client,
+    url: String,
+    tags_url: String,
+    model: String,
+    batching: Batching,
+}
+
+impl OllamaEmbedder {
+    pub fn new(config: OllamaConfig) -> Result<Self> {
+        let client = Client::builder().timeout(HTTP_TIMEOUT).build()?;
+        let base_url = config.base_url.trim_end_matches('/');
+        Ok(Self {
+            client,
+            url: format!("{}/api/embed", base_url),
+            tags_url: format!("{}/api/tags", base_url),
+            model: config.model,
+            batching: config.batching,
+        })
+    }
+}
+
+#[async_trait]
+impl Embedder for OllamaEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn provider(&self) -> &str {
+        "ollama"
+    }
+
+    async fn health(&self) -> Result<()> {
+        get_ok(self.client.get(&self.tags_url), &self.tags_url).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_in_batches(texts, self.batching, |batch| self.embed_request(batch)).await
+    }
+}
+
+impl OllamaEmbedder {
+    /// One `/api/embed` request for all of `texts`.
+    async fn embed_request(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = EmbedRequest {
+            model: &self.model,
+            input: texts,
+        };
+        let response: EmbedResponse =
+            post_json(self.client.post(&self.url).json(&request), &self.url).await?;
+        Ok(response.embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock;
+
+    #[tokio::test]
+    async fn posts_batch_to_api_embed() {
+        let (base, request) =
+            mock::serve_once(200, r#"{"model":"nomic","embeddings":[[0.5],[0.25]]}"#).await;
+        let embedder =
+            OllamaEmbedder::new(OllamaConfig::new(format!("{}/", base), "nomic")).unwrap();
+
+        let vectors = embedder
+            .embed_batch(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(vectors, vec![vec![0.5], vec![0.25]]);
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /api/embed "));
+        let body: serde_json::Value =
+            serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"model": "nomic", "input": ["a", "b"]})
+        );
+    }
+}
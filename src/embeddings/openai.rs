@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{embed_in_batches, env_var, get_ok, post_json, Batching, Embedder, HTTP_TIMEOUT};
+
+/// Path of the embeddings endpoint on OpenAI-compatible servers.
+pub const OPENAI_EMBEDDINGS_PATH: &str = "/v1/embeddings";
+
+/// Where and how to reach an OpenAI-compatible embeddings endpoint (OpenAI, vLLM,
+/// llama.cpp server, LM Studio, ...).
+#[derive(Debug, Clone)]
+pub struct OpenAIConfig {
+    pub base_url: String,
+    pub path: String,
+    /// Path probed by health checks; by default `path` with its trailing `embeddings`
+    /// replaced by `models`.
+    pub models_path: Option<String>,
+    pub api_key: Option<String>,
+    pub model: String,
+    /// Requested output size, for models that support shortening their vectors.
+    pub dimensions: Option<usize>,
+    pub batching: Batching,
+}
+
+impl OpenAIConfig {
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            path: OPENAI_EMBEDDINGS_PATH.to_string(),
+            models_path: None,
+            api_key: None,
+            model: model.into(),
+            dimensions: None,
+            batching: Batching::default(),
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    pub fn with_models_path(mut self, path: impl Into<String>) -> Self {
+        self.models_path = Some(path.into());
+        self
+    }
+
+    /// Path of the model list next to the embeddings endpoint, e.g. `/api/v1/models`
+    /// for `/api/v1/embeddings`; `/v1/models` when `path` does not end in `embeddings`.
+    fn resolved_models_path(&self) -> String {
+        if let Some(path) = &self.models_path {
+            return path.clone();
+        }
+        match self.path.trim_end_matches('/').strip_suffix("embeddings") {
+            Some(prefix) => format!("{}models", prefix),
+            None => "/v1/models".to_string(),
+        }
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    pub fn with_batching(mut self, batching: Batching) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// Read `OPENAI_BASE_URL` and `OPENAI_EMBEDDING_MODEL` (both required) plus the
+    /// optional `OPENAI_EMBEDDING_PATH`, `OPENAI_MODELS_PATH`, `OPENAI_API_KEY` and
+    /// `OPENAI_EMBEDDING_DIMENSIONS`.
+    pub fn from_env() -> Result<Self> {
+        let base_url = env_var("OPENAI_BASE_URL")
+            .ok_or_else(|| anyhow!("OPENAI_BASE_URL is required for the openai provider"))?;
+        let model = env_var("OPENAI_EMBEDDING_MODEL")
+            .ok_or_else(|| anyhow!("OPENAI_EMBEDDING_MODEL is required for the openai provider"))?;
+        let mut config = Self::new(base_url, model).with_batching(Batching::from_env()?);
+        if let Some(path) = env_var("OPENAI_EMBEDDING_PATH") {
+            config = config.with_path(path);
+        }
+        if let Some(path) = env_var("OPENAI_MODELS_PATH") {
+            config = config.with_models_path(path);
+        }
+        if let Some(key) = env_var("OPENAI_API_KEY") {
+            config = config.with_api_key(key);
+        }
+        if let Some(dimensions) = env_var("OPENAI_EMBEDDING_DIMENSIONS") {
+            config =
+                config.with_dimensions(dimensions.parse().map_err(|_| {
+                    anyhow!("OPENAI_EMBEDDING_DIMENSIONS must be a positive integer")
+                })?);
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    input: &'a [String],
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: Option<usize>,
+    embedding: Vec<f32>,
+}
+
+pub struct OpenAIEmbedder {
+    client: Client,
+    url: String,
+    models_url: String,
+    api_key: Option<String>,
+    model: String,
+    dimensions: Option<usize>,
+    batching: Batching,
+}
+
+impl OpenAIEmbedder {
+    pub fn new(config: OpenAIConfig) -> Result<Self> {
+        let client = Client::builder().timeout(HTTP_TIMEOUT).build()?;
+        let base_url = config.base_url.trim_end_matches('/');
+        let path = config.path.trim_start_matches('/');
+        let models_path = config.resolved_models_path();
+        Ok(Self {
+            client,
+            url: format!("{}/{}", base_url, path),
+            models_url: format!("{}/{}", base_url, models_path.trim_start_matches('/')),
+            api_key: config.api_key,
+            model: config.model,
+            dimensions: config.dimensions,
+            batching: config.batching,
+        })
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+
+    fn provider(&self) -> &str {
+        "openai"
+    }
+
+    async fn health(&self) -> Result<()> {
+        let mut builder = self.client.get(&self.models_url);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        get_ok(builder, &self.models_url).await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_in_batches(texts, self.batching, |batch| self.embed_request(batch)).await
+    }
+}
+
+impl OpenAIEmbedder {
+    /// One request for all of `texts`.
+    async fn embed_request(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = EmbeddingRequest {
+            input: texts,
+            model: &self.model,
+            dimensions: self.dimensions,
+        };
+        let mut builder = self.client.post(&self.url);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        let response: EmbeddingResponse = post_json(builder.json(&request), &self.url).await?;
+
+        // Servers may answer out of order; `index` says which input each vector is for.
+        let mut data = response.data;
+        if data.iter().all(|d| d.index.is_some()) {
+            data.sort_by_key(|d| d.index);
+        }
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock;
+
+    #[tokio::test]
+    async fn sends_auth_path_and_dimensions_and_orders_by_index() {
+        let (base, request) = mock::serve_once(
+            200,
+            r#"{"data":[{"index":1,"embedding":[0.0,1.0]},{"index":0,"embedding":[1.0,0.0]}]}"#,
+        )
+        .await;
+        let embedder = OpenAIEmbedder::new(
+            OpenAIConfig::new(base, "text-embed")
+                .with_path("/api/v2/embed")
+                .with_api_key("secret")
+                .with_dimensions(2),
+        )
+        .unwrap();
+
+        let vectors = embedder
+            .embed_batch(&["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /api/v2/embed "));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+        let body: serde_json::Value =
+            serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"input": ["a", "b"], "model": "text-embed", "dimensions": 2})
+        );
+    }
+
+    #[tokio::test]
+    async fn health_probes_the_models_list_next_to_the_embeddings_path() {
+        let (base, request) = mock::serve_once(200, r#"{"data":[]}"#).await;
+        let embedder = OpenAIEmbedder::new(
+            OpenAIConfig::new(base, "text-embed").with_path("/api/v1/embeddings"),
+        )
+        .unwrap();
+        embedder.health().await.unwrap();
+        assert!(request.await.unwrap().starts_with("GET /api/v1/models "));
+
+        let (base, request) = mock::serve_once(200, "{}").await;
+        let embedder = OpenAIEmbedder::new(
+            OpenAIConfig::new(base, "text-embed")
+                .with_path("/embed")
+                .with_models_path("/healthz"),
+        )
+        .unwrap();
+        embedder.health().await.unwrap();
+        assert!(request.await.unwrap().starts_with("GET /healthz "));
+
+        let config = OpenAIConfig::new("http://x", "m");
+        assert_eq!(config.resolved_models_path(), "/v1/models");
+        let config = config.with_path("/api/v2/embed");
+        assert_eq!(config.resolved_models_path(), "/v1/models");
+    }
+
+    #[tokio::test]
+    async fn reports_http_errors_with_body() {
+        let (base, _request) = mock::serve_once(401, r#"{"error":"bad key"}"#).await;
+        let embedder = OpenAIEmbedder::new(OpenAIConfig::new(base, "m")).unwrap();
+        let err = embedder.embed_batch(&["a".to_string()]).await.unwrap_err();
+        assert!(err.to_string().contains("401"));
+        assert!(err.to_string().contains("bad key"));
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+
+use super::env_var;
+
+/// What a text is embedded as; many retrieval models are trained with a different
+/// prefix for each side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbedRole {
+    Query,
+    Document,
+}
+
+/// Query and document templates of one model. `{text}` marks where the text goes and
+/// `{instruction}` where an instruction-tuned model expects its task description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    pub query: String,
+    pub document: String,
+    /// Task description used when the namespace sets none.
+    pub instruction: String,
+}
+
+impl PromptTemplate {
+    pub fn new(query: impl Into<String>, document: impl Into<String>) -> Self {
+        Self {
+            query: query.into(),
+            document: document.into(),
+            instruction: String::new(),
+        }
+    }
+
+    pub fn with_instruction(mut self, instruction: impl Into<String>) -> Self {
+        self.instruction = instruction.into();
+        self
+    }
+
+    /// Text sent as-is for both roles.
+    pub fn raw() -> Self {
+        Self::new("{text}", "{text}")
+    }
+
+    /// The template the model was trained with, matched on its id; raw text for
+    /// models without one.
+    pub fn for_model(model: &str) -> Self {
+        let id = model.to_lowercase();
+        let name = id.rsplit('/').next().unwrap_or(&id);
+        const SEARCH: &str =
+            "Given a web search query, retrieve relevant passages that answer the query";
+        const BGE_QUERY: &str = "Represent this sentence for searching relevant passages: {text}";
+        if name.contains("qwen3-embedding") {
+            Self::new("Instruct: {instruction}\nQuery:{text}", "{text}").with_instruction(SEARCH)
+        } else if name.contains("e5") && name.contains("instruct") {
+            Self::new("Instruct: {instruction}\nQuery: {text}", "{text}").with_instruction(SEARCH)
+        } else if name.starts_with("e5-") || name.contains("-e5-") {
+            Self::new("query: {text}", "passage: {text}")
+        } else if (name.starts_with("bge-") && name.contains("-en"))
+            || name.starts_with("mxbai-embed-large")
+        {
+            Self::new(BGE_QUERY, "{text}")
+        } else if name.starts_with("nomic-embed-text") {
+            Self::new("search_query: {text}", "search_document: {text}")
+        } else if name.starts_with("embeddinggemma") {
+            Self::new(
+                "task: search result | query: {text}",
+                "title: none | text: {text}",
+            )
+        } else {
+            Self::raw()
+        }
+    }
+
+    /// `text` wrapped for `role`; `instruction` replaces the default task description.
+    pub fn apply(&self, role: EmbedRole, text: &str, instruction: Option<&str>) -> String {
+        let template = match role {
+            EmbedRole::Query => &self.query,
+            EmbedRole::Document => &self.document,
+        };
+        let instruction = instruction.unwrap_or(&self.instruction);
+        let fill = |part: &str| part.replace("{instruction}", instruction);
+        match template.split_once("{text}") {
+            Some((before, after)) => format!("{}{}{}", fill(before), text, fill(after)),
+            None => format!("{}{}", fill(template), text),
+        }
+    }
+}
+
+/// How the pipeline picks templates: built in per model, with any part replaced by
+/// an override, or raw text when disabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompts {
+    pub enabled: bool,
+    pub query: Option<String>,
+    pub document: Option<String>,
+    pub instruction: Option<String>,
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            query: None,
+            document: None,
+            instruction: None,
+        }
+    }
+}
+
+impl Prompts {
+    /// Send text as-is whatever the model.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_query_template(mut self, template: impl Into<String>) -> Self {
+        self.query = Some(template.into());
+        self
+    }
+
+    pub fn with_document_template(mut self, template: impl Into<String>) -> Self {
+        self.document = Some(template.into());
+        self
+    }
+
+    pub fn with_instruction(mut self, instruction: impl Into<String>) -> Self {
+        self.instruction = Some(instruction.into());
+        self
+    }
+
+    /// `EMBED_PROMPTS=off` disables templates; `EMBED_QUERY_TEMPLATE`,
+    /// `EMBED_DOCUMENT_TEMPLATE` and `EMBED_INSTRUCTION` override those parts of the
+    /// built-in template.
+    pub fn from_env() -> Result<Self> {
+        if let Some(value) = env_var("EMBED_PROMPTS") {
+            match value.trim().to_lowercase().as_str() {
+                "on" | "true" | "1" => {}
+                "off" | "false" | "0" => return Ok(Self::disabled()),
+                other => return Err(anyhow!("EMBED_PROMPTS must be on or off, not '{}'", other)),
+            }
+        }
+        Ok(Self {
+            enabled: true,
+            query: env_var("EMBED_QUERY_TEMPLATE"),
+            document: env_var("EMBED_DOCUMENT_TEMPLATE"),
+            instruction: env_var("EMBED_INSTRUCTION"),
+        })
+    }
+
+    pub fn template(&self, model: &str) -> PromptTemplate {
+        if !self.enabled {
+            return PromptTemplate::raw();
+        }
+        let builtin = PromptTemplate::for_model(model);
+        PromptTemplate {
+            query: self.query.clone().unwrap_or(builtin.query),
+            document: self.document.clone().unwrap_or(builtin.document),
+            instruction: self.instruction.clone().unwrap_or(builtin.instruction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_get_their_trained_prefixes() {
+        let e5 = PromptTemplate::for_model("intfloat/multilingual-e5-base");
+        assert_eq!(e5.apply(EmbedRole::Query, "cats", None), "query: cats");
+        assert_eq!(e5.apply(EmbedRole::Document, "cats", None), "passage: cats");
+
+        let qwen = PromptTemplate::for_model("Qwen/Qwen3-Embedding-4B");
+        assert_eq!(
+            qwen.apply(EmbedRole::Query, "cats", Some("Find vet notes")),
+            "Instruct: Find vet notes\nQuery:cats"
+        );
+        assert_eq!(qwen.apply(EmbedRole::Document, "cats", None), "cats");
+
+        let bge = PromptTemplate::for_model("Xenova/bge-small-en-v1.5");
+        assert!(bge
+            .apply(EmbedRole::Query, "cats", None)
+            .starts_with("Represent"));
+        assert_eq!(
+            PromptTemplate::for_model("BAAI/bge-m3"),
+            PromptTemplate::raw()
+        );
+    }
+
+    #[test]
+    fn text_is_never_treated_as_a_placeholder() {
+        let template = PromptTemplate::new("{instruction}: {text}", "{text}").with_instruction("Q");
+        assert_eq!(
+            template.apply(EmbedRole::Query, "{instruction}", None),
+            "Q: {instruction}"
+        );
+        assert_eq!(
+            PromptTemplate::new("prefix ", "").apply(EmbedRole::Query, "x", None),
+            "prefix x"
+        );
+        assert_eq!(
+            Prompts::disabled().template("intfloat/e5-base-v2"),
+            PromptTemplate::raw()
+        );
+        let custom = Prompts::default()
+            .with_query_template("search: {text}")
+            .template("intfloat/e5-base-v2");
+        assert_eq!(custom.apply(EmbedRole::Query, "x", None), "search: x");
+        assert_eq!(custom.apply(EmbedRole::Document, "x", None), "passage: x");
+    }
+}
@@ -0,0 +1,716 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use super::{env_var, parse_positive, Embedder, EmbedderStatus, Reranker, TokenLimit};
+use crate::storage::{format_millis, now_millis};
+
+/// How [`ResilientEmbedder`] retries and when it stops calling its primary provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resilience {
+    /// Extra attempts after a failed call, waiting `backoff`, `2 * backoff`, ... between.
+    pub retries: u32,
+    pub backoff: Duration,
+    /// Consecutive failed calls that open the circuit.
+    pub failure_threshold: u32,
+    /// How long an open circuit skips the primary before letting a call through again.
+    pub cooldown: Duration,
+    /// Interval of the background health probe while the circuit is open.
+    pub probe_interval: Duration,
+}
+
+impl Default for Resilience {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(250),
+            failure_threshold: 3,
+            cooldown: Duration::from_secs(30),
+            probe_interval: Duration::from_secs(15),
+        }
+    }
+}
+
+impl Resilience {
+    /// Defaults overridden by `EMBED_RETRIES`, `EMBED_CIRCUIT_THRESHOLD`,
+    /// `EMBED_CIRCUIT_COOLDOWN_SECS` and `EMBED_PROBE_INTERVAL_SECS`.
+    pub fn from_env() -> Result<Self> {
+        let mut resilience = Self::default();
+        if let Some(retries) = env_var("EMBED_RETRIES") {
+            resilience.retries = retries
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("EMBED_RETRIES must be a non-negative integer"))?;
+        }
+        if let Some(threshold) = env_var("EMBED_CIRCUIT_THRESHOLD") {
+            resilience.failure_threshold =
+                parse_positive("EMBED_CIRCUIT_THRESHOLD", &threshold)? as u32;
+        }
+        if let Some(cooldown) = env_var("EMBED_CIRCUIT_COOLDOWN_SECS") {
+            resilience.cooldown = Duration::from_secs(parse_positive(
+                "EMBED_CIRCUIT_COOLDOWN_SECS",
+                &cooldown,
+            )? as u64);
+        }
+        if let Some(interval) = env_var("EMBED_PROBE_INTERVAL_SECS") {
+            resilience.probe_interval =
+                Duration::from_secs(parse_positive("EMBED_PROBE_INTERVAL_SECS", &interval)? as u64);
+        }
+        Ok(resilience)
+    }
+}
+
+/// Circuit breaker state reported by `embedder_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// The primary provider serves every call.
+    Closed,
+    /// The primary is skipped until the cooldown ends or the health probe succeeds.
+    Open,
+    /// The cooldown is over; the next call tries the primary again.
+    HalfOpen,
+}
+
+/// Health of one primary service (embedding or reranking) behind a [`ResilientEmbedder`].
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub primary: String,
+    pub primary_model: String,
+    pub fallback: Option<String>,
+    pub fallback_model: Option<String>,
+    pub consecutive_failures: u32,
+    /// Times the circuit has opened since startup.
+    pub trips: u64,
+    pub last_error: Option<String>,
+    pub last_success: Option<String>,
+    /// When an open circuit lets the next call through to the primary.
+    pub retry_at: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    retry_at_ms: Option<i64>,
+    trips: u64,
+    last_error: Option<String>,
+    last_success_ms: Option<i64>,
+}
+
+impl Breaker {
+    fn state(&self) -> CircuitState {
+        match self.open_until {
+            None => CircuitState::Closed,
+            Some(until) if Instant::now() < until => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// The remote services a [`ResilientEmbedder`] calls. Each has its own circuit, so a
+/// reranker that is down does not take embedding off the primary, or the reverse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    Embed,
+    Rerank,
+}
+
+impl Service {
+    fn label(self) -> &'static str {
+        match self {
+            Service::Embed => "embedder",
+            Service::Rerank => "reranker",
+        }
+    }
+}
+
+/// Wraps a remote provider with retries, circuit breakers and an optional fallback
+/// embedder and reranker. While a circuit is open, calls go to the fallbacks or fail
+/// fast without them (search then uses cosine order); a background probe closes the
+/// circuit as soon as the primary answers again. Embedding and reranking are guarded
+/// separately.
+pub struct ResilientEmbedder {
+    primary: Arc<dyn Embedder>,
+    reranker: Option<Arc<dyn Reranker>>,
+    fallback: Option<Arc<dyn Embedder>>,
+    fallback_reranker: Option<Arc<dyn Reranker>>,
+    resilience: Resilience,
+    embed_breaker: Mutex<Breaker>,
+    rerank_breaker: Mutex<Breaker>,
+}
+
+impl ResilientEmbedder {
+    pub fn new(primary: Arc<dyn Embedder>) -> Self {
+        Self {
+            primary,
+            reranker: None,
+            fallback: None,
+            fallback_reranker: None,
+            resilience: Resilience::default(),
+            embed_breaker: Mutex::new(Breaker::default()),
+            rerank_breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    /// Rerank through the primary's server, guarded by a circuit of its own.
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = Some(reranker);
+        self
+    }
+
+    /// Embed with `fallback` while the primary is unavailable.
+    pub fn with_fallback(mut self, fallback: Arc<dyn Embedder>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    /// Rerank with `reranker` while the primary's reranker is unavailable.
+    pub fn with_fallback_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.fallback_reranker = Some(reranker);
+        self
+    }
+
+    pub fn with_resilience(mut self, resilience: Resilience) -> Self {
+        self.resilience = resilience;
+        self
+    }
+
+    /// Probe the primary services once and keep probing in the background while a
+    /// circuit is open. When the first probe of a service fails and it has a fallback,
+    /// start on the fallback instead of waiting for calls to fail.
+    pub async fn start(self: Arc<Self>) -> Arc<Self> {
+        for service in [Service::Embed, Service::Rerank] {
+            let Some(result) = self.health_of(service).await else {
+                continue;
+            };
+            if let Err(e) = result {
+                if self.has_fallback(service) {
+                    tracing::warn!(
+                        "{} {} is unreachable, using the fallback until it answers: {}",
+                        self.primary.provider(),
+                        service.label(),
+                        e
+                    );
+                    self.trip(service, &e);
+                } else {
+                    tracing::warn!(
+                        "{} {} is unreachable: {}",
+                        self.primary.provider(),
+                        service.label(),
+                        e
+                    );
+                }
+            }
+        }
+        let weak = Arc::downgrade(&self);
+        tokio::spawn(probe_loop(weak, self.resilience.probe_interval));
+        self
+    }
+
+    /// Probe every primary service whose circuit is not closed; a healthy answer closes
+    /// it. Returns the state of the embedding circuit.
+    pub async fn probe(&self) -> CircuitState {
+        for service in [Service::Embed, Service::Rerank] {
+            if self.lock(service).state() == CircuitState::Closed {
+                continue;
+            }
+            match self.health_of(service).await {
+                Some(Ok(())) => {
+                    tracing::info!(
+                        "{} {} is reachable again; closing the circuit",
+                        self.primary.provider(),
+                        service.label()
+                    );
+                    self.record_success(service);
+                }
+                Some(Err(e)) => tracing::debug!(
+                    "{} {} probe failed: {}",
+                    self.primary.provider(),
+                    service.label(),
+                    e
+                ),
+                None => {}
+            }
+        }
+        self.lock(Service::Embed).state()
+    }
+
+    /// State of the embedding circuit.
+    pub fn circuit_status(&self) -> CircuitStatus {
+        self.status_of(
+            Service::Embed,
+            self.primary.model_id(),
+            self.fallback.as_ref().map(|f| f.provider()),
+            self.fallback.as_ref().map(|f| f.model_id()),
+        )
+    }
+
+    /// State of the reranking circuit, if the primary reranks.
+    pub fn rerank_circuit_status(&self) -> Option<CircuitStatus> {
+        let reranker = self.reranker.as_ref()?;
+        Some(self.status_of(
+            Service::Rerank,
+            reranker.reranker_model(),
+            None,
+            self.fallback_reranker.as_ref().map(|r| r.reranker_model()),
+        ))
+    }
+
+    fn status_of(
+        &self,
+        service: Service,
+        primary_model: &str,
+        fallback: Option<&str>,
+        fallback_model: Option<&str>,
+    ) -> CircuitStatus {
+        let breaker = self.lock(service);
+        let state = breaker.state();
+        CircuitStatus {
+            state,
+            primary: self.primary.provider().to_string(),
+            primary_model: primary_model.to_string(),
+            fallback: fallback.map(str::to_string),
+            fallback_model: fallback_model.map(str::to_string),
+            consecutive_failures: breaker.consecutive_failures,
+            trips: breaker.trips,
+            last_error: breaker.last_error.clone(),
+            last_success: breaker.last_success_ms.and_then(format_millis),
+            retry_at: match state {
+                CircuitState::Open => breaker.retry_at_ms.and_then(format_millis),
+                _ => None,
+            },
+        }
+    }
+
+    /// Health of one primary service; `None` when there is no such service.
+    async fn health_of(&self, service: Service) -> Option<Result<()>> {
+        match service {
+            Service::Embed => Some(self.primary.health().await),
+            Service::Rerank => match &self.reranker {
+                Some(reranker) => Some(reranker.health().await),
+                None => None,
+            },
+        }
+    }
+
+    fn has_fallback(&self, service: Service) -> bool {
+        match service {
+            Service::Embed => self.fallback.is_some(),
+            Service::Rerank => self.fallback_reranker.is_some(),
+        }
+    }
+
+    fn lock(&self, service: Service) -> std::sync::MutexGuard<'_, Breaker> {
+        let breaker = match service {
+            Service::Embed => &self.embed_breaker,
+            Service::Rerank => &self.rerank_breaker,
+        };
+        breaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether calls to `service` currently go to the primary.
+    fn primary_allowed(&self, service: Service) -> bool {
+        self.lock(service).state() != CircuitState::Open
+    }
+
+    fn record_success(&self, service: Service) {
+        let mut breaker = self.lock(service);
+        breaker.consecutive_failures = 0;
+        breaker.open_until = None;
+        breaker.retry_at_ms = None;
+        breaker.last_success_ms = Some(now_millis());
+    }
+
+    fn record_failure(&self, service: Service, error: &anyhow::Error) {
+        let mut breaker = self.lock(service);
+        breaker.consecutive_failures += 1;
+        breaker.last_error = Some(error.to_string());
+        let failures = breaker.consecutive_failures;
+        let half_open = breaker.state() == CircuitState::HalfOpen;
+        drop(breaker);
+        if half_open || failures >= self.resilience.failure_threshold {
+            tracing::warn!(
+                "{} {} failed {} times in a row; opening the circuit: {}",
+                self.primary.provider(),
+                service.label(),
+                failures,
+                error
+            );
+            self.trip(service, error);
+        }
+    }
+
+    fn trip(&self, service: Service, error: &anyhow::Error) {
+        let mut breaker = self.lock(service);
+        breaker.open_until = Some(Instant::now() + self.resilience.cooldown);
+        breaker.retry_at_ms = Some(now_millis() + self.resilience.cooldown.as_millis() as i64);
+        breaker.trips += 1;
+        breaker.last_error = Some(error.to_string());
+    }
+
+    /// Run `call` against the primary `service` with retries and record the outcome.
+    async fn call_primary<'a, T, F, Fut>(&'a self, service: Service, call: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>> + 'a,
+    {
+        let mut attempt = 0;
+        loop {
+            match call().await {
+                Ok(value) => {
+                    self.record_success(service);
+                    return Ok(value);
+                }
+                Err(e) if attempt < self.resilience.retries => {
+                    tracing::debug!(
+                        "{} {} call failed (attempt {}): {}",
+                        self.primary.provider(),
+                        service.label(),
+                        attempt + 1,
+                        e
+                    );
+                    tokio::time::sleep(self.resilience.backoff * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    self.record_failure(service, &e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn unavailable(&self, service: Service) -> anyhow::Error {
+        let breaker = self.lock(service);
+        anyhow!(
+            "{} {} is unavailable (circuit open): {}",
+            self.primary.provider(),
+            service.label(),
+            breaker.last_error.as_deref().unwrap_or("no answer")
+        )
+    }
+}
+
+async fn probe_loop(embedder: Weak<ResilientEmbedder>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(embedder) = embedder.upgrade() else {
+            return;
+        };
+        embedder.probe().await;
+    }
+}
+
+#[async_trait]
+impl Embedder for ResilientEmbedder {
+    /// The model new vectors currently come from.
+    fn model_id(&self) -> &str {
+        match &self.fallback {
+            Some(fallback) if !self.primary_allowed(Service::Embed) => fallback.model_id(),
+            _ => self.primary.model_id(),
+        }
+    }
+
+    fn provider(&self) -> &str {
+        match &self.fallback {
+            Some(fallback) if !self.primary_allowed(Service::Embed) => fallback.provider(),
+            _ => self.primary.provider(),
+        }
+    }
+
+    fn token_limit(&self) -> TokenLimit {
+        match &self.fallback {
+            Some(fallback) if !self.primary_allowed(Service::Embed) => fallback.token_limit(),
+            _ => self.primary.token_limit(),
+        }
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.primary.health().await
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(self.embed_with_model(texts).await?.1)
+    }
+
+    async fn embed_with_model(&self, texts: &[String]) -> Result<(String, Vec<Vec<f32>>)> {
+        if self.primary_allowed(Service::Embed) {
+            match self
+                .call_primary(Service::Embed, || self.primary.embed_batch(texts))
+                .await
+            {
+                Ok(vectors) => return Ok((self.primary.model_id().to_string(), vectors)),
+                Err(e) if self.fallback.is_none() => return Err(e),
+                Err(e) => tracing::warn!(
+                    "{} failed, embedding with the fallback: {}",
+                    self.primary.provider(),
+                    e
+                ),
+            }
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.embed_with_model(texts).await,
+            None => Err(self.unavailable(Service::Embed)),
+        }
+    }
+
+    fn status(&self) -> EmbedderStatus {
+        EmbedderStatus {
+            provider: self.provider().to_string(),
+            model: self.model_id().to_string(),
+            circuit: Some(self.circuit_status()),
+            reranker: None,
+            reranker_circuit: self.rerank_circuit_status(),
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for ResilientEmbedder {
+    /// The model results are currently reranked with.
+    fn reranker_model(&self) -> &str {
+        match (&self.reranker, &self.fallback_reranker) {
+            (Some(reranker), _) if self.primary_allowed(Service::Rerank) => {
+                reranker.reranker_model()
+            }
+            (_, Some(fallback)) => fallback.reranker_model(),
+            (Some(reranker), None) => reranker.reranker_model(),
+            (None, None) => "none",
+        }
+    }
+
+    async fn health(&self) -> Result<()> {
+        match &self.reranker {
+            Some(reranker) => reranker.health().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
+        if let Some(reranker) = self
+            .reranker
+            .as_ref()
+            .filter(|_| self.primary_allowed(Service::Rerank))
+        {
+            match self
+                .call_primary(Service::Rerank, || reranker.rerank(query, documents))
+                .await
+            {
+                Ok(ranked) => return Ok(ranked),
+                Err(e) if self.fallback_reranker.is_none() => return Err(e),
+                Err(e) => tracing::warn!(
+                    "{} rerank failed, using the fallback reranker: {}",
+                    self.primary.provider(),
+                    e
+                ),
+            }
+        }
+        match &self.fallback_reranker {
+            Some(fallback) => fallback.rerank(query, documents).await,
+            None if self.reranker.is_some() => Err(self.unavailable(Service::Rerank)),
+            None => Err(anyhow!("{} has no reranker", self.primary.provider())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Answers `[len]` per text, or fails while `down` is set.
+    struct Flaky {
+        model: &'static str,
+        down: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    impl Flaky {
+        fn new(model: &'static str, down: bool) -> Arc<Self> {
+            Arc::new(Self {
+                model,
+                down: AtomicBool::new(down),
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Embedder for Flaky {
+        fn model_id(&self) -> &str {
+            self.model
+        }
+
+        async fn health(&self) -> Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow!("connection refused"));
+            }
+            Ok(())
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.health().await?;
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        }
+    }
+
+    /// Ranks documents last to first, or fails while `down` is set.
+    struct Ranks {
+        model: &'static str,
+        down: AtomicBool,
+    }
+
+    impl Ranks {
+        fn new(model: &'static str, down: bool) -> Arc<Self> {
+            Arc::new(Self {
+                model,
+                down: AtomicBool::new(down),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Reranker for Ranks {
+        fn reranker_model(&self) -> &str {
+            self.model
+        }
+
+        async fn health(&self) -> Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow!("connection refused"));
+            }
+            Ok(())
+        }
+
+        async fn rerank(&self, _query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
+            self.health().await?;
+            Ok((0..documents.len()).rev().map(|i| (i, i as f32)).collect())
+        }
+    }
+
+    fn quick() -> Resilience {
+        Resilience {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+            probe_interval: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn opens_after_repeated_failures_and_uses_fallback() {
+        let primary = Flaky::new("big", true);
+        let fallback = Flaky::new("small", false);
+        let embedder = ResilientEmbedder::new(primary.clone())
+            .with_fallback(fallback.clone())
+            .with_resilience(quick());
+        let texts = vec!["abc".to_string()];
+
+        for _ in 0..2 {
+            let (model, vectors) = embedder.embed_with_model(&texts).await.unwrap();
+            assert_eq!((model.as_str(), vectors), ("small", vec![vec![3.0]]));
+        }
+        // Two calls with one retry each, then the circuit opened.
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 4);
+        assert_eq!(embedder.circuit_status().state, CircuitState::Open);
+        assert_eq!(embedder.model_id(), "small");
+
+        embedder.embed_batch(&texts).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 4);
+
+        primary.down.store(false, Ordering::SeqCst);
+        assert_eq!(embedder.probe().await, CircuitState::Closed);
+        let (model, _) = embedder.embed_with_model(&texts).await.unwrap();
+        assert_eq!(model, "big");
+    }
+
+    #[tokio::test]
+    async fn fails_fast_without_fallback_and_recovers_after_cooldown() {
+        let primary = Flaky::new("big", true);
+        let embedder = ResilientEmbedder::new(primary.clone()).with_resilience(Resilience {
+            retries: 0,
+            failure_threshold: 1,
+            cooldown: Duration::from_millis(20),
+            ..quick()
+        });
+        let texts = vec!["a".to_string()];
+
+        assert!(embedder.embed_batch(&texts).await.is_err());
+        let err = embedder.embed_batch(&texts).await.unwrap_err();
+        assert!(err.to_string().contains("circuit open"));
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(embedder.circuit_status().state, CircuitState::HalfOpen);
+        primary.down.store(false, Ordering::SeqCst);
+        assert!(embedder.embed_batch(&texts).await.is_ok());
+        assert_eq!(embedder.circuit_status().state, CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn reranks_with_fallback_when_primary_reranker_fails() {
+        let embedder = ResilientEmbedder::new(Flaky::new("big", false))
+            .with_reranker(Ranks::new("remote", true))
+            .with_fallback_reranker(Ranks::new("local", false))
+            .with_resilience(Resilience {
+                retries: 0,
+                failure_threshold: 1,
+                ..quick()
+            });
+        let documents = vec!["a".to_string(), "b".to_string()];
+
+        let ranked = embedder.rerank("q", &documents).await.unwrap();
+        assert_eq!(ranked[0].0, 1);
+        let rerank_circuit = embedder.rerank_circuit_status().unwrap();
+        assert_eq!(rerank_circuit.state, CircuitState::Open);
+        assert_eq!(rerank_circuit.fallback_model.as_deref(), Some("local"));
+        assert_eq!(embedder.reranker_model(), "local");
+    }
+
+    #[tokio::test]
+    async fn reranker_outage_leaves_embedding_on_the_primary() {
+        let primary = Flaky::new("big", false);
+        let reranker = Ranks::new("remote", true);
+        let embedder = Arc::new(
+            ResilientEmbedder::new(primary.clone())
+                .with_reranker(reranker.clone())
+                .with_fallback(Flaky::new("small", false))
+                .with_fallback_reranker(Ranks::new("local", false))
+                .with_resilience(quick()),
+        )
+        .start()
+        .await;
+        let texts = vec!["abc".to_string()];
+
+        // The failed startup probe sent reranking to the fallback right away.
+        assert_eq!(embedder.reranker_model(), "local");
+        for _ in 0..3 {
+            embedder.rerank("q", &texts).await.unwrap();
+        }
+        let (model, _) = embedder.embed_with_model(&texts).await.unwrap();
+        assert_eq!(model, "big");
+        assert_eq!(embedder.model_id(), "big");
+        assert_eq!(primary.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(embedder.circuit_status().state, CircuitState::Closed);
+        assert_eq!(embedder.circuit_status().consecutive_failures, 0);
+
+        let status = embedder.status();
+        assert_eq!(status.circuit.unwrap().state, CircuitState::Closed);
+        assert_eq!(status.reranker_circuit.unwrap().state, CircuitState::Open);
+
+        reranker.down.store(false, Ordering::SeqCst);
+        assert_eq!(embedder.probe().await, CircuitState::Closed);
+        let rerank_circuit = embedder.rerank_circuit_status().unwrap();
+        assert_eq!(rerank_circuit.state, CircuitState::Closed);
+        assert_eq!(embedder.reranker_model(), "remote");
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+
+use super::{env_var, parse_positive};
+
+/// Characters per token assumed when a model's tokenizer is not available. Low enough
+/// that estimated chunks rarely overrun the model.
+const CHARS_PER_TOKEN: usize = 3;
+
+/// Chunk size and overlap, in characters, for models with no known token limit.
+const FALLBACK_CHUNK_CHARS: usize = 512;
+const FALLBACK_OVERLAP_CHARS: usize = 128;
+
+/// How many tokens a model accepts and how to count them: exactly with its tokenizer,
+/// otherwise estimated from characters. Remote models have no known limit unless one
+/// is configured.
+#[derive(Clone, Default)]
+pub struct TokenLimit {
+    max_tokens: Option<usize>,
+    tokenizer: Option<Arc<Tokenizer>>,
+}
+
+impl TokenLimit {
+    /// No tokenizer and no limit; texts are never truncated.
+    pub fn unknown() -> Self {
+        Self::default()
+    }
+
+    /// A limit with counts estimated from characters.
+    pub fn estimated(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: Some(max_tokens),
+            tokenizer: None,
+        }
+    }
+
+    /// Exact counts with the model's tokenizer. Its truncation length, if any, is the
+    /// limit; truncation and padding are switched off so counts reflect the whole text.
+    pub fn exact(mut tokenizer: Tokenizer) -> Self {
+        let max_tokens = tokenizer.get_truncation().map(|t| t.max_length);
+        tokenizer.with_padding(None);
+        let _ = tokenizer.with_truncation(None);
+        Self {
+            max_tokens,
+            tokenizer: Some(Arc::new(tokenizer)),
+        }
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.tokenizer.is_some()
+    }
+
+    /// Tokens the model sees for `text`, including special tokens.
+    pub fn count(&self, text: &str) -> usize {
+        self.offsets(text, true)
+            .map(|o| o.len())
+            .unwrap_or_else(|| estimate(text))
+    }
+
+    /// `text` cut to its first `budget` tokens (special tokens not included), or `None`
+    /// if it already fits.
+    pub fn truncate(&self, text: &str, budget: usize) -> Option<String> {
+        match self.offsets(text, false) {
+            Some(offsets) if offsets.len() <= budget => None,
+            Some(offsets) => {
+                let end = budget.checked_sub(1).map_or(0, |last| offsets[last].1);
+                Some(text[..floor_boundary(text, end)].to_string())
+            }
+            None if estimate(text) <= budget => None,
+            None => Some(text.chars().take(budget * CHARS_PER_TOKEN).collect()),
+        }
+    }
+
+    /// Windows of `size` tokens, each starting `overlap` tokens before the previous one
+    /// ended.
+    pub fn split(&self, text: &str, size: usize, overlap: usize) -> Vec<String> {
+        let size = size.max(1);
+        let overlap = overlap.min(size - 1);
+        let Some(offsets) = self.offsets(text, false) else {
+            return split_chars(text, size * CHARS_PER_TOKEN, overlap * CHARS_PER_TOKEN);
+        };
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < offsets.len() {
+            let end = (start + size).min(offsets.len());
+            let from = floor_boundary(text, offsets[start].0);
+            let to = floor_boundary(text, offsets[end - 1].1);
+            chunks.push(text[from..to].to_string());
+            if end == offsets.len() {
+                break;
+            }
+            start = end - overlap;
+        }
+        chunks
+    }
+
+    /// Byte offsets of each token of `text`; `None` without a tokenizer.
+    fn offsets(&self, text: &str, special_tokens: bool) -> Option<Vec<(usize, usize)>> {
+        let tokenizer = self.tokenizer.as_ref()?;
+        match tokenizer.encode(text, special_tokens) {
+            Ok(encoding) => Some(encoding.get_offsets().to_vec()),
+            Err(e) => {
+                tracing::warn!("Cannot tokenize text, estimating its length: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Chunk sizing, as tokens of the active model. By default chunks fill the model's
+/// limit, less what its document prompt takes, and overlap by a quarter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chunking {
+    /// Largest chunk; never more than the model's limit.
+    pub chunk_tokens: Option<usize>,
+    pub overlap_tokens: Option<usize>,
+    /// Limit assumed for models that do not report one.
+    pub max_tokens: Option<usize>,
+}
+
+impl Chunking {
+    pub fn with_chunk_tokens(mut self, tokens: usize) -> Self {
+        self.chunk_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_overlap_tokens(mut self, tokens: usize) -> Self {
+        self.overlap_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, tokens: usize) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    /// `EMBED_CHUNK_TOKENS`, `EMBED_CHUNK_OVERLAP` and `EMBED_MAX_TOKENS`.
+    pub fn from_env() -> Result<Self> {
+        let mut chunking = Self::default();
+        if let Some(tokens) = env_var("EMBED_CHUNK_TOKENS") {
+            chunking.chunk_tokens = Some(parse_positive("EMBED_CHUNK_TOKENS", &tokens)?);
+        }
+        if let Some(tokens) = env_var("EMBED_CHUNK_OVERLAP") {
+            let tokens = tokens.trim().parse::<usize>().map_err(|_| {
+                anyhow::anyhow!("EMBED_CHUNK_OVERLAP must be a number, not '{}'", tokens)
+            })?;
+            chunking.overlap_tokens = Some(tokens);
+        }
+        if let Some(tokens) = env_var("EMBED_MAX_TOKENS") {
+            chunking.max_tokens = Some(parse_positive("EMBED_MAX_TOKENS", &tokens)?);
+        }
+        Ok(chunking)
+    }
+
+    /// `limit` with the configured limit filled in where the model reports none.
+    pub fn limit(&self, limit: TokenLimit) -> TokenLimit {
+        match (limit.max_tokens(), self.max_tokens) {
+            (None, Some(max_tokens)) => limit.with_max_tokens(max_tokens),
+            _ => limit,
+        }
+    }
+
+    /// Split `text` for a model with `limit` whose document prompt takes `overhead`
+    /// tokens. Without any limit or chunk size, chunks are 512 characters.
+    pub fn split(&self, limit: &TokenLimit, overhead: usize, text: &str) -> Vec<String> {
+        let size = match (self.chunk_tokens, limit.max_tokens()) {
+            (Some(chunk), Some(max)) => chunk.min(max.saturating_sub(overhead)),
+            (Some(chunk), None) => chunk,
+            (None, Some(max)) => max.saturating_sub(overhead),
+            (None, None) => {
+                return split_chars(text, FALLBACK_CHUNK_CHARS, FALLBACK_OVERLAP_CHARS);
+            }
+        }
+        .max(1);
+        let overlap = self.overlap_tokens.unwrap_or(size / 4);
+        limit.split(text, size, overlap)
+    }
+}
+
+fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn split_chars(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let overlap = overlap.min(size.saturating_sub(1));
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let end = (start + size).min(chars.len());
+        chunks.push(chars[start..end].iter().collect());
+        if end >= chars.len() {
+            break;
+        }
+        start = end - overlap;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimated_limits_chunk_and_truncate_by_characters() {
+        let text = "abcdefghij".repeat(10);
+        let limit = TokenLimit::estimated(10);
+        assert_eq!(limit.count(&text), 34);
+        assert_eq!(limit.truncate(&text, 40), None);
+        assert_eq!(limit.truncate(&text, 5).unwrap(), &text[..15]);
+
+        let chunks = Chunking::default().split(&limit, 2, &text);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 24));
+        assert_eq!(chunks[0], &text[..24]);
+        assert_eq!(chunks[1], &text[18..42]);
+        assert!(text.ends_with(chunks.last().unwrap().as_str()));
+    }
+
+    #[test]
+    fn tokenizers_split_on_token_boundaries() {
+        use tokenizers::models::wordlevel::WordLevel;
+        use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+        let vocab = [("[UNK]".to_string(), 0)].into_iter().collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        let limit = TokenLimit::exact(tokenizer).with_max_tokens(3);
+
+        let text = "one two  three four five";
+        assert!(limit.is_exact());
+        assert_eq!(limit.count(text), 5);
+        assert_eq!(limit.truncate(text, 3).unwrap(), "one two  three");
+        assert_eq!(limit.truncate(text, 5), None);
+        assert_eq!(
+            Chunking::default().split(&limit, 0, text),
+            vec!["one two  three", "four five"]
+        );
+        assert_eq!(
+            limit.split(text, 3, 1),
+            vec!["one two  three", "three four five"]
+        );
+    }
+
+    #[test]
+    fn unknown_limits_keep_character_chunks() {
+        let text = "ż".repeat(1000);
+        let chunks = Chunking::default().split(&TokenLimit::unknown(), 0, &text);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].chars().count(), 512);
+        assert_eq!(TokenLimit::unknown().truncate("short", 100), None);
+
+        let configured = Chunking::default()
+            .with_max_tokens(8)
+            .limit(TokenLimit::unknown());
+        assert_eq!(configured.max_tokens(), Some(8));
+        assert_eq!(
+            Chunking::default()
+                .with_max_tokens(8)
+                .limit(TokenLimit::estimated(4))
+                .max_tokens(),
+            Some(4)
+        );
+    }
+}
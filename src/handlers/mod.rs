@@ -1,14 +1,26 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde_json::json;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::Mutex;
-use uuid::Uuid;
+use tokio::task::JoinHandle;
 
-use crate::{embeddings::MLXBridge, rag::RAGPipeline, storage::StorageManager, ServerConfig};
+use crate::{
+    embeddings::{self, Chunking, Embedder, Prompts, Reranker},
+    rag::{RAGPipeline, UpsertItem},
+    storage::{
+        export_path_in, import_path_in, parse_timestamp, validate_collection_name, DocumentFilter,
+        ExportFormat, ListOptions, ListSort, StorageManager,
+    },
+    ServerConfig,
+};
 
 pub struct MCPServer {
     rag: Arc<RAGPipeline>,
+    /// Where `namespace_export` may write and `namespace_import` may read; tool paths
+    /// are relative to it.
+    export_dir: PathBuf,
 }
 
 impl MCPServer {
@@ -79,8 +91,10 @@ impl MCPServer {
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "path": {"type": "string"},
-                                "namespace": {"type": "string"}
+                                "namespace": {"type": "string"},
+                                "force": {"type": "boolean", "default": false}
                             },
                             "required": ["path"]
                         }
@@ -91,6 +105,7 @@ impl MCPServer {
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "text": {"type": "string"},
                                 "id": {"type": "string"},
                                 "namespace": {"type": "string"},
@@ -105,25 +120,53 @@ impl MCPServer {
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "query": {"type": "string"},
                                 "k": {"type": "integer", "default": 10},
-                                "namespace": {"type": "string"}
+                                "namespace": {"type": "string"},
+                                "version": {"type": "integer"}
                             },
                             "required": ["query"]
                         }
                     },
                     {
                         "name": "memory_upsert",
-                        "description": "Upsert a text chunk into vector memory",
+                        "description": "Upsert a text chunk into vector memory; without an id, the SHA-256 of the text is used and returned",
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "namespace": {"type": "string"},
-                                "id": {"type": "string"},
+                                "id": {"type": "string", "description": "Defaults to the SHA-256 of the text"},
                                 "text": {"type": "string"},
                                 "metadata": {"type": "object"}
                             },
-                            "required": ["namespace", "id", "text"]
+                            "required": ["namespace", "text"]
+                        }
+                    },
+                    {
+                        "name": "memory_upsert_batch",
+                        "description": "Upsert many chunks with one embedding pass and one write; returns a status per item",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "items": {
+                                    "type": "array",
+                                    "items": {
+                                        "type": "object",
+                                        "properties": {
+                                            "namespace": {"type": "string"},
+                                            "id": {"type": "string"},
+                                            "text": {"type": "string"},
+                                            "metadata": {"type": "object"}
+                                        },
+                                        "required": ["text"]
+                                    }
+                                }
+                            },
+                            "required": ["items"]
                         }
                     },
                     {
@@ -132,47 +175,325 @@ impl MCPServer {
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "namespace": {"type": "string"},
-                                "id": {"type": "string"}
+                                "id": {"type": "string"},
+                                "version": {"type": "integer"}
                             },
                             "required": ["namespace", "id"]
                         }
                     },
+                    {
+                        "name": "memory_list",
+                        "description": "List stored chunks in a namespace page by page (pass next_cursor back as cursor)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "limit": {"type": "integer", "default": 20, "maximum": 500},
+                                "cursor": {"type": "string"},
+                                "sort": {"type": "string", "enum": ["id", "time"], "default": "id"},
+                                "descending": {"type": "boolean", "default": false},
+                                "metadata": {"type": "object"},
+                                "preview": {"type": "boolean", "default": false},
+                                "trashed": {"type": "boolean", "default": false}
+                            },
+                            "required": ["namespace"]
+                        }
+                    },
                     {
                         "name": "memory_search",
                         "description": "Semantic search within a namespace",
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "namespace": {"type": "string"},
                                 "query": {"type": "string"},
-                                "k": {"type": "integer", "default": 5}
+                                "k": {"type": "integer", "default": 5},
+                                "version": {"type": "integer"}
                             },
                             "required": ["namespace", "query"]
                         }
                     },
                     {
                         "name": "memory_delete",
-                        "description": "Delete a chunk by namespace + id",
+                        "description": "Move a chunk to the trash by namespace + id (undo with memory_restore)",
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "namespace": {"type": "string"},
                                 "id": {"type": "string"}
                             },
                             "required": ["namespace", "id"]
                         }
                     },
+                    {
+                        "name": "reembed",
+                        "description": "Re-embed stored text with the active embedding model (whole collection in place, or a namespace into target_collection)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "target_collection": {"type": "string"}
+                            }
+                        }
+                    },
+                    {
+                        "name": "optimize",
+                        "description": "Compact fragments and prune old versions (all collections unless one is given)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "retention_hours": {"type": "number"}
+                            }
+                        }
+                    },
+                    {
+                        "name": "versions_list",
+                        "description": "List table versions of a collection (newest first) with timestamps and live row counts, optionally of one namespace",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "limit": {"type": "integer", "default": 20}
+                            }
+                        }
+                    },
+                    {
+                        "name": "namespace_restore_version",
+                        "description": "Restore a namespace's rows to how they were at a table version; rows written since move to the trash",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "version": {"type": "integer"}
+                            },
+                            "required": ["namespace", "version"]
+                        }
+                    },
+                    {
+                        "name": "namespace_export",
+                        "description": "Export a namespace (ids, text, metadata, vectors, timestamps) to a new JSONL or Parquet file; path is relative to the server's export directory",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "path": {"type": "string"},
+                                "format": {"type": "string", "enum": ["jsonl", "parquet"]}
+                            },
+                            "required": ["namespace", "path"]
+                        }
+                    },
+                    {
+                        "name": "namespace_import",
+                        "description": "Import a namespace export; path is relative to the server's export directory, and vectors must match the collection's model unless reembed is set",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "path": {"type": "string"},
+                                "format": {"type": "string", "enum": ["jsonl", "parquet"]},
+                                "namespace": {"type": "string"},
+                                "reembed": {"type": "boolean", "default": false}
+                            },
+                            "required": ["path"]
+                        }
+                    },
+                    {
+                        "name": "namespaces_list",
+                        "description": "List namespaces in a collection with row count, distinct source documents, last update time and embedding model",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"}
+                            }
+                        }
+                    },
+                    {
+                        "name": "collections_list",
+                        "description": "List collections (one vector table each)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {}
+                        }
+                    },
+                    {
+                        "name": "memory_delete_batch",
+                        "description": "Move many chunks to the trash in one write; returns deleted or not_found per id",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "ids": {"type": "array", "items": {"type": "string"}}
+                            },
+                            "required": ["namespace", "ids"]
+                        }
+                    },
+                    {
+                        "name": "memory_delete_where",
+                        "description": "Move chunks matching a source path, tags, metadata values and/or an update-time range to the trash; dry_run only counts them",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "path": {"type": "string"},
+                                "tags": {"type": "array", "items": {"type": "string"}},
+                                "metadata": {"type": "object"},
+                                "updated_after": {"type": ["string", "integer"]},
+                                "updated_before": {"type": ["string", "integer"]},
+                                "dry_run": {"type": "boolean", "default": false}
+                            }
+                        }
+                    },
                     {
                         "name": "memory_purge_namespace",
-                        "description": "Delete all chunks in a namespace",
+                        "description": "Move all chunks in a namespace to the trash",
                         "inputSchema": {
                             "type": "object",
                             "properties": {
+                                "collection": {"type": "string"},
                                 "namespace": {"type": "string"}
                             },
                             "required": ["namespace"]
                         }
+                    },
+                    {
+                        "name": "memory_restore",
+                        "description": "Restore trashed chunks of a namespace (all, or only the given ids)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "ids": {"type": "array", "items": {"type": "string"}}
+                            },
+                            "required": ["namespace"]
+                        }
+                    },
+                    {
+                        "name": "trash_empty",
+                        "description": "Permanently delete trashed chunks (optionally one namespace, or only those past the grace period)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "expired_only": {"type": "boolean", "default": false}
+                            }
+                        }
+                    },
+                    {
+                        "name": "memory_consolidate",
+                        "description": "Group near-duplicate chunks of a namespace by vector similarity; propose merges (dry_run) or keep the newest text of each group with the union of their metadata and trash the rest",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string"},
+                                "threshold": {"type": "number", "default": 0.92, "minimum": 0, "maximum": 1},
+                                "dry_run": {"type": "boolean", "default": true}
+                            },
+                            "required": ["namespace"]
+                        }
+                    },
+                    {
+                        "name": "namespace_settings",
+                        "description": "Show a namespace's settings, changing those that are given. dedup decides what happens to text already stored under another id: allow (default), skip or merge metadata. instruction is the task description instruction-tuned embedding models get with queries on this namespace (empty string resets it)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string", "default": "default"},
+                                "dedup": {"type": "string", "enum": ["allow", "skip", "merge"]},
+                                "instruction": {"type": "string"}
+                            }
+                        }
+                    },
+                    {
+                        "name": "kv_get",
+                        "description": "Read a value from the key/value scratchpad (exact key, no embeddings)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string", "default": "default"},
+                                "key": {"type": "string"}
+                            },
+                            "required": ["key"]
+                        }
+                    },
+                    {
+                        "name": "kv_set",
+                        "description": "Write a JSON value to the key/value scratchpad, optionally with a TTL and compare-and-swap on the current version (0 = must not exist)",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string", "default": "default"},
+                                "key": {"type": "string"},
+                                "value": {},
+                                "ttl_secs": {"type": "number"},
+                                "expected_version": {"type": "integer", "minimum": 0}
+                            },
+                            "required": ["key", "value"]
+                        }
+                    },
+                    {
+                        "name": "kv_delete",
+                        "description": "Remove a key from the key/value scratchpad, optionally only at an expected version",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string", "default": "default"},
+                                "key": {"type": "string"},
+                                "expected_version": {"type": "integer", "minimum": 0}
+                            },
+                            "required": ["key"]
+                        }
+                    },
+                    {
+                        "name": "kv_list",
+                        "description": "List live keys of a scratchpad namespace in key order, with cursor pagination",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "collection": {"type": "string"},
+                                "namespace": {"type": "string", "default": "default"},
+                                "prefix": {"type": "string"},
+                                "limit": {"type": "integer", "default": 100, "maximum": 1000},
+                                "cursor": {"type": "string"},
+                                "values": {"type": "boolean", "default": false}
+                            }
+                        }
+                    },
+                    {
+                        "name": "embedding_cache_stats",
+                        "description": "Show entries, size, cap and hit rate of the persistent embedding cache; clear drops every cached vector",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "clear": {"type": "boolean", "default": false}
+                            }
+                        }
+                    },
+                    {
+                        "name": "embedder_status",
+                        "description": "Show which embedding provider and model are active and, for remote providers, the circuit breaker state, failures and last error",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {}
+                        }
                     }
                 ]
             }),
@@ -180,18 +501,24 @@ impl MCPServer {
             "tools/call" => {
                 let tool_name = request["params"]["name"].as_str().unwrap_or("");
                 let args = &request["params"]["arguments"];
+                let collection = args["collection"].as_str();
+                let version = args["version"].as_u64();
 
                 match tool_name {
                     "rag_index" => {
                         let path = args["path"].as_str().unwrap_or("");
                         let namespace = args["namespace"].as_str();
+                        let force = args["force"].as_bool().unwrap_or(false);
                         match self
                             .rag
-                            .index_document(std::path::Path::new(path), namespace)
+                            .index_document(collection, Path::new(path), namespace, force)
                             .await
                         {
-                            Ok(_) => json!({
-                                "content": [{"type": "text", "text": format!("Indexed: {}", path)}]
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
                             }),
                             Err(e) => json!({
                                 "error": {"message": e.to_string()}
@@ -204,16 +531,18 @@ impl MCPServer {
                         let metadata = args.get("metadata").cloned().unwrap_or_else(|| json!({}));
                         let id = args
                             .get("id")
-                            .and_then(|v| v.as_str().map(|s| s.to_string()))
-                            .unwrap_or_else(|| Uuid::new_v4().to_string());
+                            .and_then(|v| v.as_str().map(|s| s.to_string()));
 
                         match self
                             .rag
-                            .index_text(namespace, id.clone(), text, metadata)
+                            .index_text(collection, namespace, id, text, metadata)
                             .await
                         {
-                            Ok(returned_id) => json!({
-                                "content": [{"type": "text", "text": format!("Indexed text with id {}", returned_id)}]
+                            Ok(status) => json!({
+                                "content": [{"type": "text", "text": match &status.duplicate_of {
+                                    Some(existing) => format!("Text already stored with id {} ({})", existing, status.status),
+                                    None => format!("Indexed text with id {}", status.id),
+                                }}]
                             }),
                             Err(e) => json!({
                                 "error": {"message": e.to_string()}
@@ -225,7 +554,11 @@ impl MCPServer {
                         let k = args["k"].as_u64().unwrap_or(10) as usize;
                         let namespace = args["namespace"].as_str();
 
-                        match self.rag.search_inner(namespace, query, k).await {
+                        match self
+                            .rag
+                            .search_at(collection, namespace, query, k, version)
+                            .await
+                        {
                             Ok(results) => json!({
                                 "content": [{
                                     "type": "text",
@@ -245,11 +578,48 @@ impl MCPServer {
 
                         match self
                             .rag
-                            .memory_upsert(namespace, id.clone(), text, metadata)
+                            .memory_upsert(collection, namespace, id, text, metadata)
                             .await
                         {
-                            Ok(_) => json!({
-                                "content": [{"type": "text", "text": format!("Upserted {}", id)}]
+                            Ok(status) => json!({
+                                "content": [{"type": "text", "text": match &status.duplicate_of {
+                                    Some(existing) => format!("Text already stored with id {} ({})", existing, status.status),
+                                    None => format!("Upserted {}", status.id),
+                                }}]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "memory_upsert_batch" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let items = args["items"]
+                            .as_array()
+                            .map(|items| {
+                                items
+                                    .iter()
+                                    .map(|item| UpsertItem {
+                                        namespace: item["namespace"]
+                                            .as_str()
+                                            .unwrap_or(namespace)
+                                            .to_string(),
+                                        id: item["id"].as_str().map(str::to_string),
+                                        text: item["text"].as_str().unwrap_or("").to_string(),
+                                        metadata: item
+                                            .get("metadata")
+                                            .cloned()
+                                            .unwrap_or_else(|| json!({})),
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                            .unwrap_or_default();
+                        match self.rag.memory_upsert_batch(collection, items).await {
+                            Ok(statuses) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&statuses).unwrap_or_default()
+                                }]
                             }),
                             Err(e) => json!({
                                 "error": {"message": e.to_string()}
@@ -259,7 +629,11 @@ impl MCPServer {
                     "memory_get" => {
                         let namespace = args["namespace"].as_str().unwrap_or("default");
                         let id = args["id"].as_str().unwrap_or("");
-                        match self.rag.memory_get(namespace, id).await {
+                        match self
+                            .rag
+                            .memory_get_at(collection, namespace, id, version)
+                            .await
+                        {
                             Ok(Some(doc)) => json!({
                                 "content": [{"type": "text", "text": serde_json::to_string(&doc).unwrap_or_default()}]
                             }),
@@ -271,11 +645,46 @@ impl MCPServer {
                             }),
                         }
                     }
+                    "memory_list" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let result = async {
+                            let options = ListOptions {
+                                sort: args["sort"]
+                                    .as_str()
+                                    .map(str::parse)
+                                    .transpose()?
+                                    .unwrap_or(ListSort::Id),
+                                descending: args["descending"].as_bool().unwrap_or(false),
+                                limit: args["limit"].as_u64().unwrap_or(20) as usize,
+                                cursor: args["cursor"].as_str().map(str::to_string),
+                                metadata: args["metadata"].as_object().cloned(),
+                                preview: args["preview"].as_bool().unwrap_or(false),
+                                trashed: args["trashed"].as_bool().unwrap_or(false),
+                            };
+                            self.rag.memory_list(collection, namespace, &options).await
+                        }
+                        .await;
+                        match result {
+                            Ok(page) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&page).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
                     "memory_search" => {
                         let namespace = args["namespace"].as_str().unwrap_or("default");
                         let query = args["query"].as_str().unwrap_or("");
                         let k = args["k"].as_u64().unwrap_or(5) as usize;
-                        match self.rag.memory_search(namespace, query, k).await {
+                        match self
+                            .rag
+                            .search_at(collection, Some(namespace), query, k, version)
+                            .await
+                        {
                             Ok(results) => json!({
                                 "content": [{
                                     "type": "text",
@@ -290,9 +699,74 @@ impl MCPServer {
                     "memory_delete" => {
                         let namespace = args["namespace"].as_str().unwrap_or("default");
                         let id = args["id"].as_str().unwrap_or("");
-                        match self.rag.memory_delete(namespace, id).await {
+                        match self.rag.memory_delete(collection, namespace, id).await {
                             Ok(deleted) => json!({
-                                "content": [{"type": "text", "text": format!("Deleted {} rows", deleted)}]
+                                "content": [{"type": "text", "text": format!("Moved {} rows to trash", deleted)}]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "memory_delete_batch" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let keys = args["ids"]
+                            .as_array()
+                            .map(|ids| {
+                                ids.iter()
+                                    .filter_map(|id| id.as_str())
+                                    .map(|id| (namespace.to_string(), id.to_string()))
+                                    .collect::<Vec<_>>()
+                            })
+                            .unwrap_or_default();
+                        match self.rag.memory_delete_batch(collection, keys).await {
+                            Ok(statuses) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&statuses).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "memory_delete_where" => {
+                        let dry_run = args["dry_run"].as_bool().unwrap_or(false);
+                        let result = async {
+                            let bound = |key: &str| {
+                                args.get(key)
+                                    .filter(|v| !v.is_null())
+                                    .map(parse_timestamp)
+                                    .transpose()
+                            };
+                            let filter = DocumentFilter {
+                                namespace: args["namespace"].as_str().map(str::to_string),
+                                path: args["path"].as_str().map(str::to_string),
+                                tags: args["tags"]
+                                    .as_array()
+                                    .map(|tags| {
+                                        tags.iter()
+                                            .filter_map(|t| t.as_str())
+                                            .map(str::to_string)
+                                            .collect()
+                                    })
+                                    .unwrap_or_default(),
+                                metadata: args["metadata"].as_object().cloned(),
+                                updated_after: bound("updated_after")?,
+                                updated_before: bound("updated_before")?,
+                            };
+                            self.rag
+                                .memory_delete_where(collection, &filter, dry_run)
+                                .await
+                        }
+                        .await;
+                        match result {
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
                             }),
                             Err(e) => json!({
                                 "error": {"message": e.to_string()}
@@ -301,15 +775,370 @@ impl MCPServer {
                     }
                     "memory_purge_namespace" => {
                         let namespace = args["namespace"].as_str().unwrap_or("default");
-                        match self.rag.purge_namespace(namespace).await {
+                        match self.rag.purge_namespace(collection, namespace).await {
                             Ok(deleted) => json!({
-                                "content": [{"type": "text", "text": format!("Purged namespace '{}', removed {} rows", namespace, deleted)}]
+                                "content": [{"type": "text", "text": format!("Purged namespace '{}', moved {} rows to trash", namespace, deleted)}]
                             }),
                             Err(e) => json!({
                                 "error": {"message": e.to_string()}
                             }),
                         }
                     }
+                    "memory_restore" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let ids = args["ids"].as_array().map(|ids| {
+                            ids.iter()
+                                .filter_map(|id| id.as_str())
+                                .map(str::to_string)
+                                .collect::<Vec<_>>()
+                        });
+                        match self
+                            .rag
+                            .memory_restore(collection, namespace, ids.as_deref())
+                            .await
+                        {
+                            Ok(restored) => json!({
+                                "content": [{"type": "text", "text": format!("Restored {} rows in namespace '{}'", restored, namespace)}]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "trash_empty" => {
+                        let namespace = args["namespace"].as_str();
+                        let expired_only = args["expired_only"].as_bool().unwrap_or(false);
+                        match self
+                            .rag
+                            .trash_empty(collection, namespace, expired_only)
+                            .await
+                        {
+                            Ok(removed) => json!({
+                                "content": [{"type": "text", "text": format!("Permanently deleted {} trashed rows", removed)}]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "memory_consolidate" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let threshold = args["threshold"].as_f64().unwrap_or(0.92) as f32;
+                        let dry_run = args["dry_run"].as_bool().unwrap_or(true);
+                        match self
+                            .rag
+                            .memory_consolidate(collection, namespace, threshold, dry_run)
+                            .await
+                        {
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "namespace_settings" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let result = async {
+                            let dedup = args["dedup"].as_str().map(str::parse).transpose()?;
+                            let instruction = args["instruction"].as_str().map(str::to_string);
+                            self.rag
+                                .namespace_settings(collection, namespace, dedup, instruction)
+                        }
+                        .await;
+                        match result {
+                            Ok(settings) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&settings).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "kv_get" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let key = args["key"].as_str().unwrap_or("");
+                        match self.rag.kv_get(collection, namespace, key) {
+                            Ok(entry) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&entry).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "kv_set" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let key = args["key"].as_str().unwrap_or("");
+                        let result = async {
+                            if args.get("value").is_none() {
+                                return Err(anyhow!("value is required"));
+                            }
+                            let ttl = match args["ttl_secs"].as_f64() {
+                                Some(secs) if secs > 0.0 => Some(
+                                    Duration::try_from_secs_f64(secs)
+                                        .map_err(|e| anyhow!("Invalid ttl_secs: {}", e))?,
+                                ),
+                                Some(_) => return Err(anyhow!("ttl_secs must be positive")),
+                                None => None,
+                            };
+                            self.rag.kv_set(
+                                collection,
+                                namespace,
+                                key,
+                                args["value"].clone(),
+                                ttl,
+                                args["expected_version"].as_u64(),
+                            )
+                        }
+                        .await;
+                        match result {
+                            Ok(entry) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&entry).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "kv_delete" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let key = args["key"].as_str().unwrap_or("");
+                        let expected = args["expected_version"].as_u64();
+                        match self.rag.kv_delete(collection, namespace, key, expected) {
+                            Ok(deleted) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": json!({"key": key, "deleted": deleted}).to_string()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "kv_list" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        match self.rag.kv_list(
+                            collection,
+                            namespace,
+                            args["prefix"].as_str(),
+                            args["limit"].as_u64().unwrap_or(100) as usize,
+                            args["cursor"].as_str(),
+                            args["values"].as_bool().unwrap_or(false),
+                        ) {
+                            Ok(page) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&page).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "reembed" => {
+                        let namespace = args["namespace"].as_str();
+                        let target = args["target_collection"].as_str();
+                        match self.rag.reembed(collection, namespace, target).await {
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "optimize" => {
+                        let result = async {
+                            let retention = match args["retention_hours"].as_f64() {
+                                Some(hours) => Some(
+                                    Duration::try_from_secs_f64(hours.max(0.0) * 3600.0)
+                                        .map_err(|e| anyhow!("Invalid retention_hours: {}", e))?,
+                                ),
+                                None => None,
+                            };
+                            self.rag.optimize(collection, retention).await
+                        }
+                        .await;
+                        match result {
+                            Ok(reports) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&reports).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "versions_list" => {
+                        let limit = args["limit"].as_u64().unwrap_or(20) as usize;
+                        let namespace = args["namespace"].as_str();
+                        match self.rag.list_versions(collection, namespace, limit).await {
+                            Ok(versions) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&versions).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "namespace_restore_version" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        match version {
+                            Some(version) => match self
+                                .rag
+                                .restore_namespace(collection, namespace, version)
+                                .await
+                            {
+                                Ok(restored) => json!({
+                                    "content": [{"type": "text", "text": format!("Restored namespace '{}' to version {} ({} rows)", namespace, version, restored)}]
+                                }),
+                                Err(e) => json!({
+                                    "error": {"message": e.to_string()}
+                                }),
+                            },
+                            None => json!({
+                                "error": {"message": "version is required"}
+                            }),
+                        }
+                    }
+                    "namespace_export" => {
+                        let namespace = args["namespace"].as_str().unwrap_or("default");
+                        let result = async {
+                            let path = export_path_in(
+                                &self.export_dir,
+                                args["path"]
+                                    .as_str()
+                                    .ok_or_else(|| anyhow!("path is required"))?,
+                            )
+                            .await?;
+                            let format = ExportFormat::resolve(args["format"].as_str(), &path)?;
+                            self.rag
+                                .export_namespace(collection, namespace, &path, format)
+                                .await
+                        }
+                        .await;
+                        match result {
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "namespace_import" => {
+                        let namespace = args["namespace"].as_str();
+                        let reembed = args["reembed"].as_bool().unwrap_or(false);
+                        let result = async {
+                            let raw = args["path"]
+                                .as_str()
+                                .ok_or_else(|| anyhow!("path is required"))?;
+                            let path = import_path_in(&self.export_dir, raw).await?;
+                            let format =
+                                ExportFormat::resolve(args["format"].as_str(), Path::new(raw))?;
+                            self.rag
+                                .import_namespace(collection, &path, format, namespace, reembed)
+                                .await
+                        }
+                        .await;
+                        match result {
+                            Ok(report) => json!({
+                                "content": [{
+                                    "type": "text",
+                                    "text": serde_json::to_string(&report).unwrap_or_default()
+                                }]
+                            }),
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "namespaces_list" => match self.rag.list_namespaces(collection).await {
+                        Ok(namespaces) => json!({
+                            "content": [{
+                                "type": "text",
+                                "text": serde_json::to_string(&namespaces).unwrap_or_default()
+                            }]
+                        }),
+                        Err(e) => json!({
+                            "error": {"message": e.to_string()}
+                        }),
+                    },
+                    "embedding_cache_stats" => {
+                        let result = async {
+                            let cleared = if args["clear"].as_bool().unwrap_or(false) {
+                                Some(self.rag.clear_embedding_cache().await?)
+                            } else {
+                                None
+                            };
+                            anyhow::Ok((cleared, self.rag.embedding_cache_stats()))
+                        }
+                        .await;
+                        match result {
+                            Ok((cleared, stats)) => {
+                                let mut body = json!(stats);
+                                if let Some(cleared) = cleared {
+                                    body["cleared"] = json!(cleared);
+                                }
+                                json!({
+                                    "content": [{
+                                        "type": "text",
+                                        "text": body.to_string()
+                                    }]
+                                })
+                            }
+                            Err(e) => json!({
+                                "error": {"message": e.to_string()}
+                            }),
+                        }
+                    }
+                    "embedder_status" => {
+                        let status = self.rag.embedder_status();
+                        json!({
+                            "content": [{
+                                "type": "text",
+                                "text": serde_json::to_string(&status).unwrap_or_default()
+                            }]
+                        })
+                    }
+                    "collections_list" => match self.rag.list_collections().await {
+                        Ok(collections) => json!({
+                            "content": [{
+                                "type": "text",
+                                "text": serde_json::to_string(&collections).unwrap_or_default()
+                            }]
+                        }),
+                        Err(e) => json!({
+                            "error": {"message": e.to_string()}
+                        }),
+                    },
                     _ => json!({"error": {"message": "Unknown tool"}}),
                 }
             }
@@ -365,21 +1194,212 @@ impl MCPServer {
 }
 
 pub async fn create_server(config: ServerConfig) -> Result<MCPServer> {
-    // Initialize components
-    let mlx_bridge = match MLXBridge::new().await {
-        Ok(mlx) => Some(mlx),
-        Err(e) => {
-            tracing::warn!(
-                "MLX bridge unavailable, falling back to fastembed only: {}",
-                e
-            );
-            None
+    let (embedder, reranker) = embeddings::default_providers().await?;
+    create_server_with(config, embedder, reranker).await
+}
+
+/// Like [`create_server`], with caller-supplied embedding and reranking providers.
+pub async fn create_server_with(
+    config: ServerConfig,
+    embedder: Arc<dyn Embedder>,
+    reranker: Option<Arc<dyn Reranker>>,
+) -> Result<MCPServer> {
+    let optimize_interval = config.optimize_interval;
+    let export_dir = PathBuf::from(shellexpand::tilde(&config.export_dir).as_ref());
+    let rag = create_pipeline_with(config, embedder, reranker).await?;
+
+    if let Some(interval) = optimize_interval {
+        spawn_optimize_schedule(rag.clone(), interval);
+    }
+
+    Ok(MCPServer { rag, export_dir })
+}
+
+/// Optimize every collection once per `interval`, starting one interval from now.
+fn spawn_optimize_schedule(rag: Arc<RAGPipeline>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires immediately; wait a full interval before optimizing.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match rag.optimize(None, None).await {
+                Ok(reports) => {
+                    tracing::info!(
+                        "Scheduled optimize finished for {} collections",
+                        reports.len()
+                    )
+                }
+                Err(e) => tracing::warn!("Scheduled optimize failed: {}", e),
+            }
         }
-    };
-    let mlx_bridge = Arc::new(Mutex::new(mlx_bridge));
-    let storage = Arc::new(StorageManager::new(config.cache_mb, &config.db_path).await?);
+    })
+}
+
+/// Build the storage + embedding pipeline without the JSON-RPC layer (used by the CLI
+/// maintenance subcommands).
+pub async fn create_pipeline(config: ServerConfig) -> Result<Arc<RAGPipeline>> {
+    let (embedder, reranker) = embeddings::default_providers().await?;
+    create_pipeline_with(config, embedder, reranker).await
+}
+
+pub async fn create_pipeline_with(
+    config: ServerConfig,
+    embedder: Arc<dyn Embedder>,
+    reranker: Option<Arc<dyn Reranker>>,
+) -> Result<Arc<RAGPipeline>> {
+    validate_collection_name(&config.collection_name)?;
+    let storage = Arc::new(
+        StorageManager::new(config.cache_mb, &config.db_path)
+            .await?
+            .with_default_collection(config.collection_name.clone())
+            .with_version_retention(config.version_retention)
+            .with_trash_retention(config.trash_retention)
+            .with_embedding_cache_limit((config.embedding_cache_mb * 1024 * 1024) as u64),
+    );
     storage.ensure_collection().await?;
-    let rag = Arc::new(RAGPipeline::new(mlx_bridge, storage).await?);
+    let mut rag = RAGPipeline::new(embedder, storage)
+        .with_prompts(Prompts::from_env()?)
+        .with_chunking(Chunking::from_env()?);
+    if let Some(reranker) = reranker {
+        rag = rag.with_reranker(reranker);
+    }
+    let rag = Arc::new(rag);
+    if let Err(e) = rag.check_collection(None).await {
+        tracing::warn!("{}; writes and searches on it will be rejected", e);
+    }
+
+    Ok(rag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock::FakeEmbedder;
+    use crate::storage::{content_hash, mock::temp_storage};
+
+    #[tokio::test]
+    async fn upsert_without_an_id_replies_with_the_generated_one() {
+        let (dir, storage) = temp_storage().await;
+        let server = MCPServer {
+            rag: Arc::new(RAGPipeline::new(
+                Arc::new(FakeEmbedder::new("fake", 4)),
+                Arc::new(storage),
+            )),
+            export_dir: dir.path().join("exports"),
+        };
+
+        let reply = server
+            .handle_request(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": {
+                    "name": "memory_upsert",
+                    "arguments": {"namespace": "ns", "text": "remember this"}
+                }
+            }))
+            .await;
+        assert_eq!(
+            reply["result"]["content"][0]["text"],
+            format!("Upserted {}", content_hash(b"remember this"))
+        );
+
+        let tools = server
+            .handle_request(json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+            .await;
+        let upsert = tools["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["name"] == "memory_upsert")
+            .unwrap();
+        assert_eq!(
+            upsert["inputSchema"]["required"],
+            json!(["namespace", "text"])
+        );
+    }
+
+    #[tokio::test]
+    async fn kv_set_rejects_ttls_too_long_to_represent() {
+        let (dir, storage) = temp_storage().await;
+        let server = MCPServer {
+            rag: Arc::new(RAGPipeline::new(
+                Arc::new(FakeEmbedder::new("fake", 4)),
+                Arc::new(storage),
+            )),
+            export_dir: dir.path().join("exports"),
+        };
+        let kv_set = |ttl_secs: f64| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": {
+                    "name": "kv_set",
+                    "arguments": {"key": "k", "value": 1, "ttl_secs": ttl_secs}
+                }
+            })
+        };
+
+        let reply = server.handle_request(kv_set(1e300)).await;
+        assert!(reply["result"]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("ttl_secs"));
 
-    Ok(MCPServer { rag })
+        // Representable but past the end of time: the deadline saturates.
+        let reply = server.handle_request(kv_set(1e18)).await;
+        let entry: serde_json::Value =
+            serde_json::from_str(reply["result"]["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(entry["expires_at"], i64::MAX);
+    }
+
+    #[tokio::test]
+    async fn optimize_rejects_retention_too_long_to_represent() {
+        let (dir, storage) = temp_storage().await;
+        let server = MCPServer {
+            rag: Arc::new(RAGPipeline::new(
+                Arc::new(FakeEmbedder::new("fake", 4)),
+                Arc::new(storage),
+            )),
+            export_dir: dir.path().join("exports"),
+        };
+        let reply = server
+            .handle_request(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": {"name": "optimize", "arguments": {"retention_hours": 1e300}}
+            }))
+            .await;
+        assert!(reply["result"]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("retention_hours"));
+    }
+
+    #[tokio::test]
+    async fn scheduled_optimize_empties_expired_trash() {
+        let (_dir, storage) = temp_storage().await;
+        let storage = Arc::new(storage.with_trash_retention(Duration::ZERO));
+        let rag = Arc::new(RAGPipeline::new(
+            Arc::new(FakeEmbedder::new("fake", 4)),
+            storage.clone(),
+        ));
+        rag.memory_upsert(None, "ns", "a".into(), "alpha".into(), json!({}))
+            .await
+            .unwrap();
+        rag.memory_delete(None, "ns", "a").await.unwrap();
+
+        let schedule = spawn_optimize_schedule(rag, Duration::from_millis(50));
+        let emptied = tokio::time::timeout(Duration::from_secs(10), async {
+            while !storage.row_states(None, None).await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await;
+        schedule.abort();
+        assert!(emptied.is_ok(), "trash was not emptied by the schedule");
+    }
 }
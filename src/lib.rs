@@ -4,9 +4,10 @@ pub mod rag;
 pub mod storage;
 
 use anyhow::Result;
+use std::time::Duration;
 use tracing::Level;
 
-pub use handlers::{create_server, MCPServer};
+pub use handlers::{create_server, create_server_with, MCPServer};
 
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
@@ -19,6 +20,25 @@ pub struct ServerConfig {
     /// Path for embedded vector store (LanceDB)
     pub db_path: String,
 
+    /// Size cap in MB of the persistent embedding cache (0 disables it)
+    pub embedding_cache_mb: usize,
+
+    /// Default collection (Lance table) used when a call does not name one
+    pub collection_name: String,
+
+    /// Table versions younger than this are kept when optimizing
+    pub version_retention: Duration,
+
+    /// Deleted rows stay in the trash, restorable, for at least this long
+    pub trash_retention: Duration,
+
+    /// Run `optimize` on all collections at this interval (disabled when `None`)
+    pub optimize_interval: Option<Duration>,
+
+    /// Directory that `namespace_export` tool calls write into and `namespace_import`
+    /// tool calls read from
+    pub export_dir: String,
+
     /// Default log level to use when wiring tracing
     pub log_level: Level,
 }
@@ -33,6 +53,12 @@ impl Default for ServerConfig {
             ],
             cache_mb: 4096,
             db_path: "~/.mcp-servers/mcp_memex/lancedb".to_string(),
+            embedding_cache_mb: storage::DEFAULT_EMBEDDING_CACHE_MB,
+            collection_name: storage::DEFAULT_COLLECTION.to_string(),
+            version_retention: storage::DEFAULT_VERSION_RETENTION,
+            trash_retention: storage::DEFAULT_TRASH_RETENTION,
+            optimize_interval: None,
+            export_dir: "~/.mcp-servers/mcp_memex/exports".to_string(),
             log_level: Level::INFO,
         }
     }
@@ -43,6 +69,21 @@ impl ServerConfig {
         self.db_path = db_path.into();
         self
     }
+
+    pub fn with_collection_name(mut self, collection_name: impl Into<String>) -> Self {
+        self.collection_name = collection_name.into();
+        self
+    }
+
+    pub fn with_export_dir(mut self, export_dir: impl Into<String>) -> Self {
+        self.export_dir = export_dir.into();
+        self
+    }
+
+    pub fn with_optimize_interval(mut self, interval: Duration) -> Self {
+        self.optimize_interval = Some(interval);
+        self
+    }
 }
 
 /// Helper to build and run the stdin/stdout server for library consumers.
@@ -61,5 +102,8 @@ mod tests {
         assert!(cfg.features.contains(&"filesystem".to_string()));
         assert_eq!(cfg.cache_mb, 4096);
         assert_eq!(cfg.db_path, "~/.mcp-servers/mcp_memex/lancedb");
+        assert_eq!(cfg.collection_name, "mcp_documents");
+        assert!(cfg.optimize_interval.is_none());
+        assert_eq!(cfg.export_dir, "~/.mcp-servers/mcp_memex/exports");
     }
 }
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use serde::Serialize;
+use serde_json::json;
+
+use super::{cosine, RAGPipeline};
+use crate::storage::{merge_metadata, ChromaDocument};
+
+/// Largest namespace `memory_consolidate` compares pairwise.
+pub const CONSOLIDATE_MAX_ROWS: usize = 5000;
+
+/// Outcome of [`RAGPipeline::memory_consolidate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsolidateReport {
+    pub namespace: String,
+    pub threshold: f32,
+    pub dry_run: bool,
+    pub rows: usize,
+    pub clusters: Vec<Cluster>,
+    /// Rows folded into a kept row (moved to the trash unless `dry_run`).
+    pub merged: usize,
+}
+
+/// One group of near-duplicates: `keep` is the newest row, `merged` the rest.
+#[derive(Debug, Clone, Serialize)]
+pub struct Cluster {
+    pub keep: String,
+    pub merged: Vec<ClusterMember>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterMember {
+    pub id: String,
+    pub similarity: f32,
+}
+
+impl RAGPipeline {
+    /// Group rows of a namespace whose vectors are at least `threshold` cosine-similar
+    /// to the newest row of their group. Unless `dry_run`, each group becomes its newest
+    /// row carrying the union of the group's metadata, and the others go to the trash.
+    pub async fn memory_consolidate(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        threshold: f32,
+        dry_run: bool,
+    ) -> Result<ConsolidateReport> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(anyhow!("threshold must be between 0 and 1"));
+        }
+        let mut stream = self
+            .storage
+            .stream_documents(collection, Some(namespace))
+            .await?;
+        let mut docs: Vec<ChromaDocument> = Vec::new();
+        while let Some(mut batch) = stream.try_next().await? {
+            docs.append(&mut batch);
+            if docs.len() > CONSOLIDATE_MAX_ROWS {
+                return Err(anyhow!(
+                    "Namespace '{}' has more than {} rows; consolidate a smaller namespace",
+                    namespace,
+                    CONSOLIDATE_MAX_ROWS
+                ));
+            }
+        }
+        // Newest first, so each group is led by its most recent text.
+        docs.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(a.id.cmp(&b.id)));
+
+        let vectors = docs.iter().map(|d| d.embedding.clone()).collect::<Vec<_>>();
+        let groups =
+            tokio::task::spawn_blocking(move || cluster_by_leader(&vectors, threshold)).await?;
+
+        let clusters = groups
+            .iter()
+            .map(|group| Cluster {
+                keep: docs[group[0].0].id.clone(),
+                merged: group[1..]
+                    .iter()
+                    .map(|&(i, similarity)| ClusterMember {
+                        id: docs[i].id.clone(),
+                        similarity,
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        let merged = clusters.iter().map(|c| c.merged.len()).sum();
+        let report = ConsolidateReport {
+            namespace: namespace.to_string(),
+            threshold,
+            dry_run,
+            rows: docs.len(),
+            clusters,
+            merged,
+        };
+        if dry_run || groups.is_empty() {
+            return Ok(report);
+        }
+
+        let spec = self
+            .storage
+            .collection_embedding(collection)
+            .await?
+            .ok_or_else(|| anyhow!("Collection has no table"))?;
+        let mut kept = Vec::with_capacity(groups.len());
+        let mut folded = Vec::new();
+        for group in &groups {
+            // Older metadata first so the kept row's own values win.
+            let mut metadata = json!({});
+            for &(i, _) in group.iter().skip(1).rev() {
+                merge_metadata(&mut metadata, &docs[i].metadata);
+                folded.push((namespace.to_string(), docs[i].id.clone()));
+            }
+            let keep = &docs[group[0].0];
+            merge_metadata(&mut metadata, &keep.metadata);
+            let ids = group[1..]
+                .iter()
+                .map(|&(i, _)| docs[i].id.clone())
+                .collect::<Vec<_>>();
+            merge_metadata(&mut metadata, &json!({ "consolidated_from": ids }));
+            kept.push(ChromaDocument {
+                metadata,
+                updated_at: None,
+                ..keep.clone()
+            });
+        }
+        self.storage
+            .upsert_to_store(collection, kept, &spec)
+            .await?;
+        self.storage.delete_keys(collection, &folded).await?;
+        tracing::info!(
+            "Consolidated {} rows into {} in namespace '{}'",
+            report.merged,
+            groups.len(),
+            namespace
+        );
+        Ok(report)
+    }
+}
+
+/// Greedy leader clustering over `vectors`, which are ordered by priority: each row not
+/// yet taken leads a group of the later untaken rows at least `threshold` similar to
+/// it. Returns groups of two or more as (index, similarity to the leader), leader first.
+fn cluster_by_leader(vectors: &[Vec<f32>], threshold: f32) -> Vec<Vec<(usize, f32)>> {
+    let mut taken = vec![false; vectors.len()];
+    let mut groups = Vec::new();
+    for leader in 0..vectors.len() {
+        if taken[leader] {
+            continue;
+        }
+        taken[leader] = true;
+        let mut group = vec![(leader, 1.0)];
+        for other in leader + 1..vectors.len() {
+            if taken[other] {
+                continue;
+            }
+            let similarity = cosine(&vectors[leader], &vectors[other]);
+            if similarity >= threshold {
+                taken[other] = true;
+                group.push((other, similarity));
+            }
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock::FakeEmbedder;
+    use crate::storage::mock::temp_storage;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn near_duplicates_fold_into_the_newest_row() {
+        let (_dir, storage) = temp_storage().await;
+        let rag = RAGPipeline::new(Arc::new(FakeEmbedder::new("fake", 4)), Arc::new(storage));
+        // "abc" and "abcabc" embed to parallel vectors; "xxxx" to an orthogonal one.
+        let rows = [
+            ("old", "abc", json!({"tags": ["a"], "source": "notes"})),
+            ("other", "xxxx", json!({})),
+            ("new", "abcabc", json!({"tags": ["b"]})),
+        ];
+        for (id, text, metadata) in rows {
+            rag.memory_upsert(None, "ns", id.into(), text.into(), metadata)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        for threshold in [-0.1, 1.5] {
+            assert!(rag
+                .memory_consolidate(None, "ns", threshold, true)
+                .await
+                .is_err());
+        }
+
+        let report = rag
+            .memory_consolidate(None, "ns", 0.99, true)
+            .await
+            .unwrap();
+        assert_eq!((report.rows, report.merged), (3, 1));
+        assert_eq!(report.clusters[0].keep, "new");
+        assert_eq!(report.clusters[0].merged[0].id, "old");
+        let old = rag.memory_get(None, "ns", "old").await.unwrap().unwrap();
+        assert_eq!(old.metadata, json!({"tags": ["a"], "source": "notes"}));
+        let new = rag.memory_get(None, "ns", "new").await.unwrap().unwrap();
+        assert_eq!(new.metadata, json!({"tags": ["b"]}));
+
+        let report = rag
+            .memory_consolidate(None, "ns", 0.99, false)
+            .await
+            .unwrap();
+        assert_eq!(report.merged, 1);
+        assert!(rag.memory_get(None, "ns", "old").await.unwrap().is_none());
+        assert!(rag.memory_get(None, "ns", "other").await.unwrap().is_some());
+        let new = rag.memory_get(None, "ns", "new").await.unwrap().unwrap();
+        assert_eq!(new.text, "abcabc");
+        assert_eq!(
+            new.metadata,
+            json!({"tags": ["a", "b"], "source": "notes", "consolidated_from": ["old"]})
+        );
+
+        let restored = rag
+            .memory_restore(None, "ns", Some(&["old".to_string()]))
+            .await
+            .unwrap();
+        assert_eq!(restored, 1);
+        let old = rag.memory_get(None, "ns", "old").await.unwrap().unwrap();
+        assert_eq!(old.text, "abc");
+    }
+
+    #[test]
+    fn leader_groups_only_rows_close_to_it() {
+        let vectors = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.99, 0.1],
+            vec![0.7, 0.7],
+        ];
+        let groups = cluster_by_leader(&vectors, 0.95);
+        assert_eq!(groups.len(), 1);
+        let members = groups[0].iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        assert_eq!(members, vec![0, 2]);
+        assert!(cluster_by_leader(&vectors, 1.0).is_empty());
+    }
+}
@@ -1,100 +1,463 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
 use pdf_extract;
 use serde_json::json;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 use crate::{
-    embeddings::{FastEmbedder, MLXBridge},
-    storage::{ChromaDocument, StorageManager},
+    embeddings::{Chunking, EmbedRole, Embedder, EmbedderStatus, Prompts, Reranker, TokenLimit},
+    storage::{
+        content_hash, merge_metadata, now_millis, read_export, ChromaDocument, DedupPolicy,
+        DocumentFilter, DocumentPage, DocumentRecord, EmbeddingCacheStats, EmbeddingSpec,
+        ExportFormat, ExportReport, KvEntry, KvPage, ListOptions, NamespaceSettings,
+        NamespaceStats, OptimizeReport, StorageManager, VersionInfo,
+    },
 };
 
+mod consolidate;
+
+pub use consolidate::{Cluster, ClusterMember, ConsolidateReport, CONSOLIDATE_MAX_ROWS};
+
 const DEFAULT_NAMESPACE: &str = "rag";
 
+/// Rows embedded per call by re-embedding and imports.
+const REEMBED_BATCH: usize = 64;
+
+/// Matched rows named in a `memory_delete_where` report.
+const DELETE_SAMPLE: usize = 20;
+
 pub struct RAGPipeline {
-    mlx_bridge: Arc<Mutex<Option<MLXBridge>>>,
+    embedder: Arc<dyn Embedder>,
+    reranker: Option<Arc<dyn Reranker>>,
+    prompts: Prompts,
+    chunking: Chunking,
     storage: Arc<StorageManager>,
-    fast: Arc<FastEmbedder>,
 }
 
 impl RAGPipeline {
-    pub async fn new(
-        mlx_bridge: Arc<Mutex<Option<MLXBridge>>>,
-        storage: Arc<StorageManager>,
-    ) -> Result<Self> {
-        let fast = Arc::new(FastEmbedder::new()?);
-        Ok(Self {
-            mlx_bridge,
+    /// Search results are ordered by cosine similarity unless a reranker is set.
+    pub fn new(embedder: Arc<dyn Embedder>, storage: Arc<StorageManager>) -> Self {
+        Self {
+            embedder,
+            reranker: None,
+            prompts: Prompts::default(),
+            chunking: Chunking::default(),
             storage,
-            fast,
-        })
+        }
     }
 
-    pub async fn index_document(&self, path: &Path, namespace: Option<&str>) -> Result<()> {
-        let text = self.extract_text(path).await?;
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = Some(reranker);
+        self
+    }
 
-        // Chunk the text
-        let chunks = self.chunk_text(&text, 512, 128)?;
+    /// Query and document templates; by default each model gets the prefixes it was
+    /// trained with.
+    pub fn with_prompts(mut self, prompts: Prompts) -> Self {
+        self.prompts = prompts;
+        self
+    }
 
-        // Embed chunks
-        let embeddings = self.embed_chunks(&chunks).await?;
+    /// How indexed files are split; by default chunks fill the active model's token
+    /// limit.
+    pub fn with_chunking(mut self, chunking: Chunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
 
+    /// Index a file, consulting the document registry: a file whose content hash and
+    /// model match the last run and whose chunks are all still stored is skipped unless
+    /// `force` is set. Chunks left over from a longer previous version are trashed.
+    pub async fn index_document(
+        &self,
+        collection: Option<&str>,
+        path: &Path,
+        namespace: Option<&str>,
+        force: bool,
+    ) -> Result<IndexReport> {
         let ns = namespace.unwrap_or(DEFAULT_NAMESPACE);
-        let mut documents = Vec::new();
-        for (i, (chunk, embedding)) in chunks.iter().zip(embeddings.iter()).enumerate() {
-            let doc = ChromaDocument {
-                id: format!("{}_{}", path.to_str().unwrap_or("unknown"), i),
+        let path_str = path.to_str().unwrap_or("unknown").to_string();
+        let bytes = tokio::fs::read(path).await?;
+        let hash = content_hash(&bytes);
+        let mtime = tokio::fs::metadata(path)
+            .await?
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as i64);
+        let model = self.active_model();
+
+        let previous = self.storage.document_record(collection, ns, &path_str)?;
+        if let Some(record) = previous.as_ref().filter(|r| {
+            !force && r.content_hash == hash && r.model == model && !r.chunk_ids.is_empty()
+        }) {
+            let keys = record
+                .chunk_ids
+                .iter()
+                .map(|id| (ns.to_string(), id.clone()))
+                .collect::<Vec<_>>();
+            let stored = self.storage.existing_keys(collection, &keys).await?;
+            if stored.len() == keys.len() {
+                tracing::debug!("Skipping unchanged document {}", path_str);
+                return Ok(IndexReport {
+                    path: path_str,
+                    namespace: ns.to_string(),
+                    status: "unchanged",
+                    chunks: keys.len(),
+                    duplicates: 0,
+                    removed: 0,
+                });
+            }
+        }
+
+        let text = self.extract_text(path).await?;
+
+        let chunks = self.chunk_text(&text);
+        let total = chunks.len();
+        let items = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| UpsertItem {
                 namespace: ns.to_string(),
-                embedding: embedding.clone(),
+                id: Some(format!("{}_{}", path_str, i)),
+                text: chunk,
                 metadata: json!({
                     "path": path.to_str(),
                     "chunk_index": i,
-                    "total_chunks": chunks.len(),
+                    "total_chunks": total,
                 }),
-                document: chunk.clone(),
-            };
-            documents.push(doc);
+            })
+            .collect();
+
+        let previous_ids = previous
+            .as_ref()
+            .map(|r| r.chunk_ids.clone())
+            .unwrap_or_default();
+        let statuses = self.write_items(collection, items, &previous_ids).await?;
+        if let Some(error) = statuses.iter().find_map(|s| s.error.clone()) {
+            return Err(anyhow!("Cannot index {}: {}", path_str, error));
         }
+        let chunk_ids = statuses
+            .iter()
+            .filter(|s| s.duplicate_of.is_none())
+            .map(|s| s.id.clone())
+            .collect::<Vec<_>>();
+        let duplicates = statuses.len() - chunk_ids.len();
 
-        self.storage.add_to_store(documents).await?;
+        let stale = previous_ids
+            .into_iter()
+            .filter(|id| !chunk_ids.contains(id))
+            .map(|id| (ns.to_string(), id))
+            .collect::<Vec<_>>();
+        let removed = if stale.is_empty() {
+            0
+        } else {
+            self.storage.delete_keys(collection, &stale).await?.len()
+        };
 
-        Ok(())
+        self.storage.put_document_record(
+            collection,
+            ns,
+            &DocumentRecord {
+                path: path_str.clone(),
+                content_hash: hash,
+                chunk_ids: chunk_ids.clone(),
+                mtime,
+                model,
+                indexed_at: now_millis(),
+            },
+        )?;
+
+        Ok(IndexReport {
+            path: path_str,
+            namespace: ns.to_string(),
+            status: "indexed",
+            chunks: chunk_ids.len(),
+            duplicates,
+            removed,
+        })
     }
 
+    /// Index one text. Without an id the row is addressed by the hash of its text, so
+    /// storing the same text again updates the same row.
     pub async fn index_text(
         &self,
+        collection: Option<&str>,
         namespace: Option<&str>,
-        id: String,
+        id: Option<String>,
         text: String,
         metadata: serde_json::Value,
-    ) -> Result<String> {
-        let ns = namespace.unwrap_or(DEFAULT_NAMESPACE).to_string();
-        let embedding = self.embed_query(&text).await?;
-        let doc = ChromaDocument {
-            id: id.clone(),
-            namespace: ns,
-            embedding,
+    ) -> Result<ItemStatus> {
+        let item = UpsertItem {
+            namespace: namespace.unwrap_or(DEFAULT_NAMESPACE).to_string(),
+            id,
+            text,
             metadata,
-            document: text,
         };
-        self.storage.add_to_store(vec![doc]).await?;
-        Ok(id)
+        let mut statuses = self.write_items(collection, vec![item], &[]).await?;
+        let status = statuses
+            .pop()
+            .ok_or_else(|| anyhow!("Nothing was written"))?;
+        match status.error {
+            Some(error) => Err(anyhow!(error)),
+            None => Ok(status),
+        }
     }
 
     pub async fn memory_upsert(
         &self,
+        collection: Option<&str>,
         namespace: &str,
         id: String,
         text: String,
         metadata: serde_json::Value,
-    ) -> Result<()> {
-        self.index_text(Some(namespace), id, text, metadata).await?;
-        Ok(())
+    ) -> Result<ItemStatus> {
+        self.index_text(collection, Some(namespace), Some(id), text, metadata)
+            .await
     }
 
-    pub async fn memory_get(&self, namespace: &str, id: &str) -> Result<Option<SearchResult>> {
-        if let Some(doc) = self.storage.get_document(namespace, id).await? {
+    /// Upsert many items with one embedding pass and one write. Invalid items are
+    /// reported and skipped; an embedding or storage failure fails the whole batch.
+    pub async fn memory_upsert_batch(
+        &self,
+        collection: Option<&str>,
+        items: Vec<UpsertItem>,
+    ) -> Result<Vec<ItemStatus>> {
+        self.write_items(collection, items, &[]).await
+    }
+
+    /// Shared write path applying each namespace's dedup policy. Rows whose id is in
+    /// `replaced` are about to be rewritten by the caller and never count as the
+    /// existing copy of a text.
+    async fn write_items(
+        &self,
+        collection: Option<&str>,
+        items: Vec<UpsertItem>,
+        replaced: &[String],
+    ) -> Result<Vec<ItemStatus>> {
+        let mut statuses = Vec::with_capacity(items.len());
+        // (index into `statuses`, document awaiting its embedding)
+        let mut pending: Vec<(usize, ChromaDocument)> = Vec::new();
+        let mut seen: HashMap<(String, String), usize> = HashMap::new();
+        for item in items {
+            let id = item
+                .id
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(|| content_hash(item.text.as_bytes()));
+            if item.text.trim().is_empty() {
+                statuses.push(ItemStatus::failed(item.namespace, id, "text is required"));
+                continue;
+            }
+            let index = statuses.len();
+            statuses.push(ItemStatus::new(
+                item.namespace.clone(),
+                id.clone(),
+                "inserted",
+            ));
+            let doc = ChromaDocument {
+                id: id.clone(),
+                namespace: item.namespace.clone(),
+                embedding: Vec::new(),
+                metadata: item.metadata,
+                document: item.text,
+                updated_at: None,
+            };
+            // A key repeated in one batch keeps its last occurrence.
+            match seen.entry((item.namespace, id)) {
+                Entry::Occupied(slot) => {
+                    let (previous, _) = std::mem::replace(&mut pending[*slot.get()], (index, doc));
+                    statuses[previous].status = "superseded";
+                    statuses[previous].error = Some("repeated later in the batch".to_string());
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(pending.len());
+                    pending.push((index, doc));
+                }
+            }
+        }
+        let (mut pending, merged) = self
+            .apply_dedup(collection, pending, &mut statuses, replaced)
+            .await?;
+        if pending.is_empty() && merged.is_empty() {
+            return Ok(statuses);
+        }
+
+        let keys = pending
+            .iter()
+            .map(|(_, d)| (d.namespace.clone(), d.id.clone()))
+            .collect::<Vec<_>>();
+        let existing = self.storage.existing_keys(collection, &keys).await?;
+
+        // One call for everything; the embedder splits it into requests itself.
+        let mut spec = None;
+        if !pending.is_empty() {
+            let texts = pending
+                .iter()
+                .map(|(_, d)| d.document.clone())
+                .collect::<Vec<_>>();
+            let (batch_spec, embeddings) = self.embed_documents(&texts).await?;
+            for ((_, doc), embedding) in pending.iter_mut().zip(embeddings) {
+                doc.embedding = embedding;
+            }
+            spec = Some(batch_spec);
+        }
+        if spec.is_none() {
+            // Only metadata merges: the stored vectors are reused as they are.
+            spec = self.storage.collection_embedding(collection).await?;
+        }
+        let indices = pending.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        let mut documents = pending.into_iter().map(|(_, d)| d).collect::<Vec<_>>();
+        documents.extend(merged);
+        if let Some(spec) = spec {
+            self.storage
+                .upsert_to_store(collection, documents, &spec)
+                .await?;
+        }
+
+        for (i, key) in indices.into_iter().zip(&keys) {
+            if existing.contains(key) {
+                statuses[i].status = "updated";
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Drop or fold pending documents whose text is already stored under another id in
+    /// a namespace with a `skip` or `merge` policy, or appears earlier in the batch.
+    /// Returns the documents still to embed and the stored rows with merged metadata.
+    async fn apply_dedup(
+        &self,
+        collection: Option<&str>,
+        pending: Vec<(usize, ChromaDocument)>,
+        statuses: &mut [ItemStatus],
+        replaced: &[String],
+    ) -> Result<(Vec<(usize, ChromaDocument)>, Vec<ChromaDocument>)> {
+        let mut policies: HashMap<String, DedupPolicy> = HashMap::new();
+        for (_, doc) in &pending {
+            if !policies.contains_key(&doc.namespace) {
+                let policy = self
+                    .storage
+                    .namespace_settings(collection, &doc.namespace)?
+                    .dedup;
+                policies.insert(doc.namespace.clone(), policy);
+            }
+        }
+        if policies.values().all(|p| *p == DedupPolicy::Allow) {
+            return Ok((pending, Vec::new()));
+        }
+
+        let writing = pending
+            .iter()
+            .map(|(_, d)| (d.namespace.clone(), d.id.clone()))
+            .collect::<HashSet<_>>();
+        let hashes = pending
+            .iter()
+            .map(|(_, d)| content_hash(d.document.as_bytes()))
+            .collect::<Vec<_>>();
+
+        // Stored rows by (namespace, hash). Rows the batch rewrites do not count.
+        let mut stored: HashMap<(String, String), Vec<ChromaDocument>> = HashMap::new();
+        for (ns, policy) in &policies {
+            if *policy == DedupPolicy::Allow {
+                continue;
+            }
+            let ns_hashes = pending
+                .iter()
+                .zip(&hashes)
+                .filter(|((_, d), _)| &d.namespace == ns)
+                .map(|(_, h)| h.clone())
+                .collect::<Vec<_>>();
+            for doc in self
+                .storage
+                .find_by_content(collection, ns, &ns_hashes)
+                .await?
+            {
+                if replaced.contains(&doc.id)
+                    || writing.contains(&(doc.namespace.clone(), doc.id.clone()))
+                {
+                    continue;
+                }
+                let hash = content_hash(doc.document.as_bytes());
+                stored.entry((ns.clone(), hash)).or_default().push(doc);
+            }
+        }
+
+        let mut kept: Vec<(usize, ChromaDocument)> = Vec::new();
+        // Index into `kept` of the first pending document with a given (namespace, hash).
+        let mut first: HashMap<(String, String), usize> = HashMap::new();
+        let mut merged_keys: HashSet<(String, String)> = HashSet::new();
+        for ((index, doc), hash) in pending.into_iter().zip(hashes) {
+            let policy = policies[&doc.namespace];
+            let key = (doc.namespace.clone(), hash);
+            if policy == DedupPolicy::Allow {
+                kept.push((index, doc));
+                continue;
+            }
+            if let Some(rows) = stored.get_mut(&key) {
+                if let Some(row) = rows.iter_mut().min_by(|a, b| a.id.cmp(&b.id)) {
+                    statuses[index].duplicate_of = Some(row.id.clone());
+                    if policy == DedupPolicy::Merge {
+                        merge_metadata(&mut row.metadata, &doc.metadata);
+                        statuses[index].status = "merged";
+                        merged_keys.insert((row.namespace.clone(), row.id.clone()));
+                    } else {
+                        statuses[index].status = "duplicate";
+                    }
+                    continue;
+                }
+            }
+            if let Some(&slot) = first.get(&key) {
+                let owner = &mut kept[slot].1;
+                statuses[index].duplicate_of = Some(owner.id.clone());
+                if policy == DedupPolicy::Merge {
+                    merge_metadata(&mut owner.metadata, &doc.metadata);
+                    statuses[index].status = "merged";
+                } else {
+                    statuses[index].status = "duplicate";
+                }
+                continue;
+            }
+            first.insert(key, kept.len());
+            kept.push((index, doc));
+        }
+        let merged = stored
+            .into_values()
+            .flatten()
+            .filter(|row| merged_keys.contains(&(row.namespace.clone(), row.id.clone())))
+            .map(|row| ChromaDocument {
+                updated_at: None,
+                ..row
+            })
+            .collect();
+        Ok((kept, merged))
+    }
+
+    pub async fn memory_get(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        id: &str,
+    ) -> Result<Option<SearchResult>> {
+        self.memory_get_at(collection, namespace, id, None).await
+    }
+
+    /// Like [`Self::memory_get`], reading the collection as of a table version.
+    pub async fn memory_get_at(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        id: &str,
+        version: Option<u64>,
+    ) -> Result<Option<SearchResult>> {
+        if let Some(doc) = self
+            .storage
+            .get_document(collection, namespace, id, version)
+            .await?
+        {
             return Ok(Some(SearchResult {
                 id: doc.id,
                 namespace: doc.namespace,
@@ -106,38 +469,275 @@ impl RAGPipeline {
         Ok(None)
     }
 
-    pub async fn memory_delete(&self, namespace: &str, id: &str) -> Result<usize> {
-        self.storage.delete_document(namespace, id).await
+    pub async fn memory_list(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        options: &ListOptions,
+    ) -> Result<DocumentPage> {
+        self.storage
+            .list_documents(collection, namespace, options)
+            .await
+    }
+
+    pub async fn memory_delete(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        id: &str,
+    ) -> Result<usize> {
+        self.storage
+            .delete_document(collection, namespace, id)
+            .await
+    }
+
+    /// Delete many `(namespace, id)` keys in one write, reporting each as `deleted` or
+    /// `not_found`.
+    pub async fn memory_delete_batch(
+        &self,
+        collection: Option<&str>,
+        keys: Vec<(String, String)>,
+    ) -> Result<Vec<ItemStatus>> {
+        let deleted = self.storage.delete_keys(collection, &keys).await?;
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let status = if deleted.contains(&key) {
+                    "deleted"
+                } else {
+                    "not_found"
+                };
+                ItemStatus::new(key.0, key.1, status)
+            })
+            .collect())
+    }
+
+    /// Delete every row selected by `filter` in one write, or only count them when
+    /// `dry_run` is set.
+    pub async fn memory_delete_where(
+        &self,
+        collection: Option<&str>,
+        filter: &DocumentFilter,
+        dry_run: bool,
+    ) -> Result<DeleteWhereReport> {
+        if filter.is_unbounded() {
+            return Err(anyhow!(
+                "memory_delete_where needs a path, tags, metadata or date filter; use memory_purge_namespace to clear a namespace"
+            ));
+        }
+        let keys = self.storage.matching_keys(collection, filter).await?;
+        let deleted = if dry_run {
+            0
+        } else {
+            self.storage.delete_keys(collection, &keys).await?.len()
+        };
+        Ok(DeleteWhereReport {
+            matched: keys.len(),
+            deleted,
+            dry_run,
+            sample: keys
+                .into_iter()
+                .take(DELETE_SAMPLE)
+                .map(|(namespace, id)| format!("{}/{}", namespace, id))
+                .collect(),
+        })
+    }
+
+    /// Undo deletes: bring trashed rows of a namespace back (all, or only `ids`).
+    pub async fn memory_restore(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        ids: Option<&[String]>,
+    ) -> Result<usize> {
+        self.storage
+            .restore_trashed(collection, namespace, ids)
+            .await
+    }
+
+    /// Permanently delete trashed rows.
+    pub async fn trash_empty(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        expired_only: bool,
+    ) -> Result<usize> {
+        self.storage
+            .empty_trash(collection, namespace, expired_only)
+            .await
+    }
+
+    /// Settings of a namespace, after applying any that are given.
+    pub fn namespace_settings(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        dedup: Option<DedupPolicy>,
+        instruction: Option<String>,
+    ) -> Result<NamespaceSettings> {
+        let mut settings = self.storage.namespace_settings(collection, namespace)?;
+        if dedup.is_none() && instruction.is_none() {
+            return Ok(settings);
+        }
+        if let Some(dedup) = dedup {
+            settings.dedup = dedup;
+        }
+        if let Some(instruction) = instruction {
+            // An empty instruction goes back to the model's default.
+            settings.instruction = Some(instruction.trim().to_string()).filter(|i| !i.is_empty());
+        }
+        self.storage
+            .set_namespace_settings(collection, namespace, &settings)?;
+        Ok(settings)
     }
 
-    pub async fn purge_namespace(&self, namespace: &str) -> Result<usize> {
-        self.storage.purge_namespace(namespace).await
+    pub fn kv_get(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<KvEntry>> {
+        self.storage.kv_get(collection, namespace, key)
+    }
+
+    pub fn kv_set(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+        value: serde_json::Value,
+        ttl: Option<Duration>,
+        expected_version: Option<u64>,
+    ) -> Result<KvEntry> {
+        self.storage
+            .kv_set(collection, namespace, key, value, ttl, expected_version)
+    }
+
+    pub fn kv_delete(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+        expected_version: Option<u64>,
+    ) -> Result<bool> {
+        self.storage
+            .kv_delete(collection, namespace, key, expected_version)
+    }
+
+    pub fn kv_list(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        prefix: Option<&str>,
+        limit: usize,
+        cursor: Option<&str>,
+        with_values: bool,
+    ) -> Result<KvPage> {
+        self.storage
+            .kv_list(collection, namespace, prefix, limit, cursor, with_values)
+    }
+
+    pub async fn purge_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+    ) -> Result<usize> {
+        self.storage.purge_namespace(collection, namespace).await
+    }
+
+    pub async fn list_collections(&self) -> Result<Vec<String>> {
+        self.storage.list_collections().await
+    }
+
+    pub async fn list_namespaces(&self, collection: Option<&str>) -> Result<Vec<NamespaceStats>> {
+        self.storage.namespace_stats(collection).await
+    }
+
+    pub async fn list_versions(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<VersionInfo>> {
+        self.storage
+            .list_versions(collection, namespace, limit)
+            .await
+    }
+
+    pub async fn restore_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        version: u64,
+    ) -> Result<usize> {
+        self.storage
+            .restore_namespace(collection, namespace, version)
+            .await
+    }
+
+    /// Compact and prune one collection, or every collection when none is given.
+    pub async fn optimize(
+        &self,
+        collection: Option<&str>,
+        retention: Option<Duration>,
+    ) -> Result<Vec<OptimizeReport>> {
+        match collection {
+            Some(c) => Ok(self
+                .storage
+                .optimize_collection(Some(c), retention)
+                .await?
+                .into_iter()
+                .collect()),
+            None => self.storage.optimize_all(retention).await,
+        }
     }
 
     pub async fn memory_search(
         &self,
+        collection: Option<&str>,
         namespace: &str,
         query: &str,
         k: usize,
     ) -> Result<Vec<SearchResult>> {
-        self.search_inner(Some(namespace), query, k).await
+        self.search_inner(collection, Some(namespace), query, k)
+            .await
     }
 
     pub async fn search(&self, query: &str, k: usize) -> Result<Vec<SearchResult>> {
-        self.search_inner(None, query, k).await
+        self.search_inner(None, None, query, k).await
     }
 
     pub async fn search_inner(
         &self,
+        collection: Option<&str>,
         namespace: Option<&str>,
         query: &str,
         k: usize,
     ) -> Result<Vec<SearchResult>> {
-        let query_embedding = self.embed_query(query).await?;
+        self.search_at(collection, namespace, query, k, None).await
+    }
+
+    /// Like [`Self::search_inner`], searching the collection as of a table version.
+    pub async fn search_at(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        query: &str,
+        k: usize,
+        version: Option<u64>,
+    ) -> Result<Vec<SearchResult>> {
+        let (spec, query_embedding) = self.embed_query(collection, namespace, query).await?;
 
         let candidates = self
             .storage
-            .search_store(namespace, query_embedding.clone(), k * 3)
+            .search_store(
+                collection,
+                namespace,
+                query_embedding.clone(),
+                k * 3,
+                &spec,
+                version,
+            )
             .await?;
 
         // Rerank if we have candidates
@@ -146,12 +746,12 @@ impl RAGPipeline {
             let metadatas: Vec<serde_json::Value> =
                 candidates.iter().map(|c| c.metadata.clone()).collect();
 
-            // Try MLX reranker; fallback to cosine if unavailable
-            let reranked = if let Some(mlx) = self.mlx_bridge.lock().await.as_mut() {
-                match mlx.rerank(query, &documents).await {
+            // Try the reranker; fallback to cosine if unavailable
+            let reranked = if let Some(reranker) = &self.reranker {
+                match reranker.rerank(query, &documents).await {
                     Ok(r) => Some(r),
                     Err(e) => {
-                        tracing::warn!("MLX rerank failed, using cosine fallback: {}", e);
+                        tracing::warn!("Rerank failed, using cosine fallback: {}", e);
                         None
                     }
                 }
@@ -163,7 +763,9 @@ impl RAGPipeline {
                 r
             } else {
                 // Cosine fallback
-                let doc_embeddings = self.ensure_doc_embeddings(&documents, &candidates).await?;
+                let doc_embeddings = self
+                    .ensure_doc_embeddings(&spec, &documents, &candidates)
+                    .await?;
                 let scores = doc_embeddings
                     .iter()
                     .enumerate()
@@ -201,6 +803,250 @@ impl RAGPipeline {
         Ok(vec![])
     }
 
+    /// Re-embed stored text with the active provider. Without a target the whole
+    /// collection, trash included, is rebuilt in a staging table that replaces it once
+    /// complete; writes made meanwhile are copied over with writes paused just before
+    /// the swap. With a target the live rows are copied there and the source is left
+    /// untouched. Rows already present in the destination are skipped, so an
+    /// interrupted run can be resumed.
+    pub async fn reembed(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        target: Option<&str>,
+    ) -> Result<ReembedReport> {
+        let source = self.storage.collection_or_default(collection)?;
+        let in_place = target.is_none();
+        if in_place && namespace.is_some() {
+            return Err(anyhow!(
+                "Re-embedding a single namespace needs a target collection; the rest of '{}' would keep the old model",
+                source
+            ));
+        }
+        let target = match target {
+            Some(t) => self.storage.collection_or_default(Some(t))?,
+            None => self.storage.staging_collection(Some(&source)).await?,
+        };
+        if target == source {
+            return Err(anyhow!("Target collection must differ from '{}'", source));
+        }
+
+        let done = self.storage.row_states(Some(&target), namespace).await?;
+        let mut report = ReembedReport {
+            source: source.clone(),
+            target: target.clone(),
+            namespace: namespace.map(str::to_string),
+            model: self.active_model(),
+            dim: 0,
+            scanned: 0,
+            embedded: 0,
+            skipped: 0,
+            swapped: false,
+        };
+
+        // Trashed rows are copied live; `catch_up` gives them back their trash state.
+        let mut stream = if in_place {
+            self.storage.stream_all_documents(Some(&source)).await?
+        } else {
+            self.storage
+                .stream_documents(Some(&source), namespace)
+                .await?
+        };
+        while let Some(docs) = stream.try_next().await? {
+            for batch in docs.chunks(REEMBED_BATCH) {
+                report.scanned += batch.len();
+                let mut pending = batch
+                    .iter()
+                    .filter(|d| !done.contains_key(&(d.namespace.clone(), d.id.clone())))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                report.skipped += batch.len() - pending.len();
+                if pending.is_empty() {
+                    continue;
+                }
+
+                let texts = pending
+                    .iter()
+                    .map(|d| d.document.clone())
+                    .collect::<Vec<_>>();
+                let (spec, embeddings) = self.embed_documents(&texts).await?;
+                for (doc, embedding) in pending.iter_mut().zip(embeddings) {
+                    doc.embedding = embedding;
+                }
+                report.embedded += pending.len();
+                self.storage
+                    .add_to_store(Some(&target), pending, &spec)
+                    .await?;
+                report.model = spec.model;
+                report.dim = spec.dim;
+            }
+            tracing::info!(
+                "Re-embed {} -> {}: {} rows scanned, {} embedded",
+                source,
+                target,
+                report.scanned,
+                report.embedded
+            );
+        }
+
+        if in_place {
+            let _paused = self.storage.pause_writes(Some(&source)).await?;
+            if self.catch_up(&source, &target, &mut report).await? {
+                self.storage.swap_collection(Some(&source), &target).await?;
+                report.swapped = true;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Copy writes made to `source` since it was streamed into the staging table and
+    /// give every row the trash state it has in `source`. Runs with writes to `source`
+    /// paused. Returns false when `source` has no rows, so there is nothing to swap in.
+    async fn catch_up(
+        &self,
+        source: &str,
+        target: &str,
+        report: &mut ReembedReport,
+    ) -> Result<bool> {
+        let current = self.storage.row_states(Some(source), None).await?;
+        if current.is_empty() {
+            return Ok(false);
+        }
+        let copied = self.storage.row_states(Some(target), None).await?;
+
+        // Every write stamps `updated_at`, so rows without one are older than the copy.
+        let changed = current
+            .iter()
+            .filter(|(key, state)| match copied.get(*key) {
+                Some(copy) => state.updated_at.is_some() && copy.updated_at != state.updated_at,
+                None => true,
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for keys in changed.chunks(REEMBED_BATCH) {
+            let mut docs = self.storage.documents_by_keys(Some(source), keys).await?;
+            if docs.is_empty() {
+                continue;
+            }
+            let texts = docs.iter().map(|d| d.document.clone()).collect::<Vec<_>>();
+            let (spec, embeddings) = self.embed_documents(&texts).await?;
+            for (doc, embedding) in docs.iter_mut().zip(embeddings) {
+                doc.embedding = embedding;
+            }
+            report.embedded += docs.len();
+            self.storage
+                .upsert_to_store(Some(target), docs, &spec)
+                .await?;
+            report.model = spec.model;
+            report.dim = spec.dim;
+        }
+
+        let removed = copied
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !removed.is_empty() {
+            self.storage.purge_keys(Some(target), &removed).await?;
+        }
+
+        // Rows written just now are live in the copy.
+        let changed = changed.into_iter().collect::<HashSet<_>>();
+        let trash = current
+            .iter()
+            .filter(|(key, state)| {
+                let copy = if changed.contains(*key) {
+                    None
+                } else {
+                    copied.get(*key).and_then(|c| c.deleted_at)
+                };
+                copy != state.deleted_at
+            })
+            .map(|(key, state)| (key.clone(), state.deleted_at))
+            .collect::<Vec<_>>();
+        self.storage.set_trash_state(Some(target), &trash).await?;
+        tracing::info!(
+            "Re-embed {} -> {}: caught up {} changed rows, {} removed, {} trash states",
+            source,
+            target,
+            changed.len(),
+            removed.len(),
+            trash.len()
+        );
+        Ok(true)
+    }
+
+    pub async fn export_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        path: &Path,
+        format: ExportFormat,
+    ) -> Result<ExportReport> {
+        self.storage
+            .export_namespace(collection, namespace, path, format)
+            .await
+    }
+
+    /// Load an export into `collection`, upserting by `(namespace, id)`. Stored vectors
+    /// are kept as-is unless `reembed` is set, in which case the text is embedded again
+    /// with the active provider.
+    pub async fn import_namespace(
+        &self,
+        collection: Option<&str>,
+        path: &Path,
+        format: ExportFormat,
+        namespace: Option<&str>,
+        reembed: bool,
+    ) -> Result<ImportReport> {
+        let target = self.storage.collection_or_default(collection)?;
+        let (header, mut documents) = read_export(path, format).await?;
+        if let Some(ns) = namespace {
+            for doc in &mut documents {
+                doc.namespace = ns.to_string();
+            }
+        }
+
+        let mut spec = EmbeddingSpec::new(header.model.clone(), header.dim);
+        if reembed {
+            for batch in documents.chunks_mut(REEMBED_BATCH) {
+                let texts = batch.iter().map(|d| d.document.clone()).collect::<Vec<_>>();
+                let (active, embeddings) = self.embed_documents(&texts).await?;
+                for (doc, embedding) in batch.iter_mut().zip(embeddings) {
+                    doc.embedding = embedding;
+                }
+                spec = active;
+            }
+        }
+
+        let rows = documents.len();
+        self.storage
+            .upsert_to_store(Some(&target), documents, &spec)
+            .await
+            .map_err(|e| {
+                if reembed {
+                    e
+                } else {
+                    anyhow!("{}; import with reembed to use the active model", e)
+                }
+            })?;
+        tracing::info!(
+            "Imported {} rows from {} into '{}'",
+            rows,
+            path.display(),
+            target
+        );
+        Ok(ImportReport {
+            collection: target,
+            namespace: namespace.unwrap_or(&header.namespace).to_string(),
+            source_model: header.model,
+            model: spec.model,
+            dim: spec.dim,
+            rows,
+            reembedded: reembed,
+        })
+    }
+
     async fn extract_text(&self, path: &Path) -> Result<String> {
         let ext = path
             .extension()
@@ -220,63 +1066,329 @@ impl RAGPipeline {
         tokio::fs::read_to_string(path).await.map_err(|e| e.into())
     }
 
-    async fn embed_chunks(&self, chunks: &[String]) -> Result<Vec<Vec<f32>>> {
-        // Try MLX first
-        if let Some(mlx) = self.mlx_bridge.lock().await.as_mut() {
-            let mut out = Vec::with_capacity(chunks.len());
-            for chunk in chunks {
-                out.push(mlx.embed(chunk).await?);
+    /// Model id of the provider embeddings are currently produced with.
+    pub fn active_model(&self) -> String {
+        self.embedder.model_id().to_string()
+    }
+
+    pub fn embedder_status(&self) -> EmbedderStatus {
+        EmbedderStatus {
+            reranker: self
+                .reranker
+                .as_ref()
+                .map(|r| r.reranker_model().to_string()),
+            ..self.embedder.status()
+        }
+    }
+
+    /// Fail early when a collection was embedded with a different model than the
+    /// active provider, instead of writing vectors that cannot be compared.
+    pub async fn check_collection(&self, collection: Option<&str>) -> Result<()> {
+        let Some(stored) = self.storage.collection_embedding(collection).await? else {
+            return Ok(());
+        };
+        let active = self.active_model();
+        if stored.model != "unknown" && stored.model != active {
+            return Err(anyhow!(
+                "Collection '{}' was embedded with '{}' ({} dims) but the active embedder is '{}'",
+                collection.unwrap_or(self.storage.get_collection_name()),
+                stored.model,
+                stored.dim,
+                active
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn embedding_cache_stats(&self) -> EmbeddingCacheStats {
+        self.storage.embedding_cache_stats()
+    }
+
+    pub async fn clear_embedding_cache(&self) -> Result<u64> {
+        self.storage.clear_embedding_cache().await
+    }
+
+    /// Embed `chunks` as `role`, wrapped in the active model's template, reusing
+    /// vectors the embedding cache holds for the model.
+    async fn embed_chunks(
+        &self,
+        chunks: &[String],
+        role: EmbedRole,
+        instruction: Option<&str>,
+    ) -> Result<(EmbeddingSpec, Vec<Vec<f32>>)> {
+        let mut model = self.embedder.model_id().to_string();
+        let prompted = self.apply_prompts(&model, chunks, role, instruction);
+        let hashes = prompted
+            .iter()
+            .map(|c| content_hash(c.as_bytes()))
+            .collect::<Vec<_>>();
+        let mut vectors = self
+            .storage
+            .cached_embeddings(&model, &hashes)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Embedding cache lookup failed: {}", e);
+                vec![None; chunks.len()]
+            });
+        let missing = (0..chunks.len())
+            .filter(|&i| vectors[i].is_none())
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            let texts = missing
+                .iter()
+                .map(|&i| prompted[i].clone())
+                .collect::<Vec<_>>();
+            let (used, embedded) = self.embed_uncached(&texts).await?;
+            if used != model {
+                // The provider switched models after the lookup; embed everything again
+                // with the new model's template.
+                let prompted = self.apply_prompts(&used, chunks, role, instruction);
+                let (used, embedded) = self.embed_uncached(&prompted).await?;
+                let hashes = prompted
+                    .iter()
+                    .map(|c| content_hash(c.as_bytes()))
+                    .collect::<Vec<_>>();
+                self.cache_vectors(&used, &hashes, &embedded).await;
+                return check_dimensions(used, embedded);
             }
-            return Ok(out);
+            let missing_hashes = missing
+                .iter()
+                .map(|&i| hashes[i].clone())
+                .collect::<Vec<_>>();
+            self.cache_vectors(&used, &missing_hashes, &embedded).await;
+            for (i, vector) in missing.into_iter().zip(embedded) {
+                vectors[i] = Some(vector);
+            }
+            model = used;
         }
+        check_dimensions(model, vectors.into_iter().flatten().collect())
+    }
 
-        // Fallback to fastembed batch
-        self.fast.embed_batch(chunks).await
+    fn apply_prompts(
+        &self,
+        model: &str,
+        texts: &[String],
+        role: EmbedRole,
+        instruction: Option<&str>,
+    ) -> Vec<String> {
+        let template = self.prompts.template(model);
+        let limit = self.token_limit();
+        let Some(max) = limit.max_tokens() else {
+            return texts
+                .iter()
+                .map(|t| template.apply(role, t, instruction))
+                .collect();
+        };
+        // Cut the text rather than the prompted text so the template stays intact.
+        let budget = max.saturating_sub(limit.count(&template.apply(role, "", instruction)));
+        let mut truncated = 0;
+        let prompted = texts
+            .iter()
+            .map(|t| match limit.truncate(t, budget) {
+                Some(cut) => {
+                    truncated += 1;
+                    template.apply(role, &cut, instruction)
+                }
+                None => template.apply(role, t, instruction),
+            })
+            .collect();
+        if truncated > 0 {
+            let role = match role {
+                EmbedRole::Query => "query",
+                EmbedRole::Document => "document",
+            };
+            tracing::warn!(
+                "Truncated {} of {} {} text(s) to the {}-token limit of {}{}",
+                truncated,
+                texts.len(),
+                role,
+                max,
+                model,
+                if limit.is_exact() { "" } else { " (estimated)" }
+            );
+        }
+        prompted
+    }
+
+    /// The active model's token limit, or the configured one if it reports none.
+    fn token_limit(&self) -> TokenLimit {
+        self.chunking.limit(self.embedder.token_limit())
+    }
+
+    /// Embed `texts` with the provider, bypassing the cache.
+    async fn embed_uncached(&self, texts: &[String]) -> Result<(String, Vec<Vec<f32>>)> {
+        let (model, embeddings) = self.embedder.embed_with_model(texts).await?;
+        if embeddings.len() != texts.len() {
+            return Err(anyhow!(
+                "Embedder '{}' returned {} vectors for {} texts",
+                model,
+                embeddings.len(),
+                texts.len()
+            ));
+        }
+        Ok((model, embeddings))
     }
 
-    async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
-        if let Some(mlx) = self.mlx_bridge.lock().await.as_mut() {
-            return mlx.embed(query).await;
+    async fn cache_vectors(&self, model: &str, hashes: &[String], vectors: &[Vec<f32>]) {
+        if let Err(e) = self.storage.cache_embeddings(model, hashes, vectors).await {
+            tracing::warn!("Cannot cache embeddings: {}", e);
         }
-        let emb = self.fast.embed_batch(&[query.to_string()]).await?;
-        Ok(emb.into_iter().next().unwrap_or_default())
+    }
+
+    async fn embed_documents(&self, texts: &[String]) -> Result<(EmbeddingSpec, Vec<Vec<f32>>)> {
+        self.embed_chunks(texts, EmbedRole::Document, None).await
+    }
+
+    /// Embed a query with the namespace's instruction, if it has one.
+    async fn embed_query(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        query: &str,
+    ) -> Result<(EmbeddingSpec, Vec<f32>)> {
+        let instruction = match namespace {
+            Some(ns) => self.storage.namespace_settings(collection, ns)?.instruction,
+            None => None,
+        };
+        let (spec, embeddings) = self
+            .embed_chunks(
+                &[query.to_string()],
+                EmbedRole::Query,
+                instruction.as_deref(),
+            )
+            .await?;
+        let embedding = embeddings
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No embedding returned"))?;
+        Ok((spec, embedding))
     }
 
     async fn ensure_doc_embeddings(
         &self,
+        spec: &EmbeddingSpec,
         documents: &[String],
         candidates: &[ChromaDocument],
     ) -> Result<Vec<Vec<f32>>> {
-        // If Chroma returned embeddings, use them; otherwise embed locally
-        let has_all = candidates.iter().all(|c| !c.embedding.is_empty());
+        // If the store returned embeddings, use them; otherwise re-embed with the same
+        // provider as the query so the scores stay comparable
+        let has_all = candidates.iter().all(|c| c.embedding.len() == spec.dim);
         if has_all {
             return Ok(candidates.iter().map(|c| c.embedding.clone()).collect());
         }
 
-        self.fast.embed_batch(documents).await
+        let (doc_spec, embeddings) = self.embed_documents(documents).await?;
+        if doc_spec != *spec {
+            return Err(anyhow!(
+                "Embedder changed from '{}' to '{}' during search",
+                spec.model,
+                doc_spec.model
+            ));
+        }
+        Ok(embeddings)
     }
 
-    fn chunk_text(&self, text: &str, chunk_size: usize, overlap: usize) -> Result<Vec<String>> {
-        let mut chunks = Vec::new();
-        let chars: Vec<char> = text.chars().collect();
+    /// Split `text` into chunks that fit the active model with its document prompt.
+    fn chunk_text(&self, text: &str) -> Vec<String> {
+        let limit = self.token_limit();
+        let template = self.prompts.template(self.embedder.model_id());
+        let overhead = limit.count(&template.apply(EmbedRole::Document, "", None));
+        self.chunking.split(&limit, overhead, text)
+    }
+}
 
-        let mut start = 0;
-        while start < chars.len() {
-            let end = (start + chunk_size).min(chars.len());
-            let chunk: String = chars[start..end].iter().collect();
-            chunks.push(chunk);
+/// Outcome of [`RAGPipeline::reembed`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReembedReport {
+    pub source: String,
+    pub target: String,
+    pub namespace: Option<String>,
+    pub model: String,
+    pub dim: usize,
+    pub scanned: usize,
+    pub embedded: usize,
+    pub skipped: usize,
+    pub swapped: bool,
+}
 
-            if end >= chars.len() {
-                break;
-            }
+/// Outcome of [`RAGPipeline::index_document`]. `status` is `indexed` or `unchanged`;
+/// `duplicates` counts chunks already stored elsewhere in the namespace and `removed`
+/// chunks of the previous version that no longer exist.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IndexReport {
+    pub path: String,
+    pub namespace: String,
+    pub status: &'static str,
+    pub chunks: usize,
+    pub duplicates: usize,
+    pub removed: usize,
+}
 
-            start = end - overlap;
+/// One entry of [`RAGPipeline::memory_upsert_batch`].
+#[derive(Debug, Clone)]
+pub struct UpsertItem {
+    pub namespace: String,
+    /// Generated when missing.
+    pub id: Option<String>,
+    pub text: String,
+    pub metadata: serde_json::Value,
+}
+
+/// Per-item result of a batch operation.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ItemStatus {
+    pub namespace: String,
+    pub id: String,
+    pub status: &'static str,
+    /// Row already holding this text, for `duplicate` and `merged` items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ItemStatus {
+    fn new(namespace: String, id: String, status: &'static str) -> Self {
+        Self {
+            namespace,
+            id,
+            status,
+            duplicate_of: None,
+            error: None,
         }
+    }
 
-        Ok(chunks)
+    fn failed(namespace: String, id: String, error: &str) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(namespace, id, "error")
+        }
     }
 }
 
+/// Outcome of [`RAGPipeline::memory_delete_where`]. `sample` lists the first matched
+/// rows as `namespace/id`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeleteWhereReport {
+    pub matched: usize,
+    pub deleted: usize,
+    pub dry_run: bool,
+    pub sample: Vec<String>,
+}
+
+/// Outcome of [`RAGPipeline::import_namespace`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ImportReport {
+    pub collection: String,
+    pub namespace: String,
+    pub source_model: String,
+    pub model: String,
+    pub dim: usize,
+    pub rows: usize,
+    pub reembedded: bool,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SearchResult {
     pub id: String,
@@ -286,6 +1398,21 @@ pub struct SearchResult {
     pub metadata: serde_json::Value,
 }
 
+/// Spec of a batch of vectors, which must all have the same dimension.
+fn check_dimensions(
+    model: String,
+    embeddings: Vec<Vec<f32>>,
+) -> Result<(EmbeddingSpec, Vec<Vec<f32>>)> {
+    let dim = embeddings.first().map(|e| e.len()).unwrap_or_default();
+    if embeddings.iter().any(|e| e.len() != dim) {
+        return Err(anyhow!(
+            "Embedder '{}' returned vectors of inconsistent dimension",
+            model
+        ));
+    }
+    Ok((EmbeddingSpec::new(model, dim), embeddings))
+}
+
 fn cosine(a: &[f32], b: &[f32]) -> f32 {
     let mut dot = 0.0_f32;
     let mut norm_a = 0.0_f32;
@@ -300,3 +1427,318 @@ fn cosine(a: &[f32], b: &[f32]) -> f32 {
     }
     dot / (norm_a.sqrt() * norm_b.sqrt())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock::FakeEmbedder;
+    use crate::storage::mock::{doc, temp_storage};
+    use async_trait::async_trait;
+    use futures::future::BoxFuture;
+    use futures::FutureExt;
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
+
+    /// Embeds like `inner`, running `hook` before the first call.
+    struct Hooked {
+        inner: FakeEmbedder,
+        hook: Mutex<Option<BoxFuture<'static, ()>>>,
+    }
+
+    #[async_trait]
+    impl Embedder for Hooked {
+        fn model_id(&self) -> &str {
+            self.inner.model_id()
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let hook = self.hook.lock().unwrap().take();
+            if let Some(hook) = hook {
+                hook.await;
+            }
+            self.inner.embed_batch(texts).await
+        }
+    }
+
+    async fn pipeline(model: &'static str, dim: usize) -> (tempfile::TempDir, RAGPipeline) {
+        let (dir, storage) = temp_storage().await;
+        let rag = RAGPipeline::new(Arc::new(FakeEmbedder::new(model, dim)), Arc::new(storage));
+        (dir, rag)
+    }
+
+    #[tokio::test]
+    async fn another_embedder_cannot_write_or_search_a_collection() {
+        let (_dir, small) = pipeline("small", 8).await;
+        small
+            .memory_upsert(None, "ns", "a".into(), "alpha".into(), json!({}))
+            .await
+            .unwrap();
+
+        let wide = RAGPipeline::new(
+            Arc::new(FakeEmbedder::new("wide", 16)),
+            small.storage.clone(),
+        );
+        assert!(wide.check_collection(None).await.is_err());
+        let err = wide
+            .memory_upsert(None, "ns", "b".into(), "beta".into(), json!({}))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("re-embed"));
+        assert!(wide.memory_search(None, "ns", "alpha", 3).await.is_err());
+
+        assert!(small.memory_get(None, "ns", "b").await.unwrap().is_none());
+        let results = small.memory_search(None, "ns", "alpha", 3).await.unwrap();
+        assert_eq!(results[0].id, "a");
+    }
+
+    #[tokio::test]
+    async fn in_place_reembed_keeps_concurrent_writes_and_the_trash() {
+        let (_dir, old) = pipeline("old", 4).await;
+        for (id, text) in [("a", "alpha"), ("b", "beta"), ("c", "gamma")] {
+            old.memory_upsert(None, "ns", id.into(), text.into(), json!({}))
+                .await
+                .unwrap();
+        }
+        old.memory_delete(None, "ns", "b").await.unwrap();
+
+        // Lands while the first batch is being re-embedded.
+        let writer = old.storage.clone();
+        let hook = async move {
+            let spec = EmbeddingSpec::new("old", 4);
+            writer
+                .upsert_to_store(None, vec![doc("ns", "late", "delta", vec![1.0; 4])], &spec)
+                .await
+                .unwrap();
+            writer.delete_document(None, "ns", "c").await.unwrap();
+        }
+        .boxed();
+        let embedder = Hooked {
+            inner: FakeEmbedder::new("new", 6),
+            hook: Mutex::new(Some(hook)),
+        };
+        let new = RAGPipeline::new(Arc::new(embedder), old.storage.clone());
+
+        let report = new.reembed(None, None, None).await.unwrap();
+        assert!(report.swapped);
+        assert_eq!(
+            new.storage.collection_embedding(None).await.unwrap(),
+            Some(EmbeddingSpec::new("new", 6))
+        );
+        for id in ["a", "late"] {
+            assert!(new.memory_get(None, "ns", id).await.unwrap().is_some());
+        }
+        for id in ["b", "c"] {
+            assert!(new.memory_get(None, "ns", id).await.unwrap().is_none());
+        }
+        assert_eq!(new.memory_restore(None, "ns", None).await.unwrap(), 2);
+        assert!(new.memory_get(None, "ns", "c").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn reads_and_restores_a_namespace_as_of_a_version() {
+        let (_dir, rag) = pipeline("fake", 8).await;
+        rag.memory_upsert(None, "ns", "a".into(), "first draft".into(), json!({}))
+            .await
+            .unwrap();
+        rag.memory_upsert(None, "other", "x".into(), "untouched".into(), json!({}))
+            .await
+            .unwrap();
+        let before = rag.list_versions(None, None, 1).await.unwrap()[0].version;
+        rag.memory_upsert(None, "ns", "a".into(), "second draft".into(), json!({}))
+            .await
+            .unwrap();
+        rag.memory_upsert(None, "ns", "b".into(), "brand new".into(), json!({}))
+            .await
+            .unwrap();
+
+        let old = rag
+            .memory_get_at(None, "ns", "a", Some(before))
+            .await
+            .unwrap();
+        assert_eq!(old.unwrap().text, "first draft");
+        assert!(rag
+            .memory_get_at(None, "ns", "b", Some(before))
+            .await
+            .unwrap()
+            .is_none());
+        let found = rag
+            .search_at(None, Some("ns"), "draft", 5, Some(before))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text, "first draft");
+
+        assert_eq!(rag.restore_namespace(None, "ns", before).await.unwrap(), 1);
+        let current = rag.memory_get(None, "ns", "a").await.unwrap();
+        assert_eq!(current.unwrap().text, "first draft");
+        assert!(rag.memory_get(None, "ns", "b").await.unwrap().is_none());
+        assert!(rag.memory_get(None, "other", "x").await.unwrap().is_some());
+
+        // The row written after the version went to the trash, not away.
+        assert_eq!(rag.memory_restore(None, "ns", None).await.unwrap(), 1);
+        assert!(rag.memory_get(None, "ns", "b").await.unwrap().is_some());
+    }
+
+    fn item(namespace: &str, id: Option<&str>, text: &str) -> UpsertItem {
+        UpsertItem {
+            namespace: namespace.to_string(),
+            id: id.map(str::to_string),
+            text: text.to_string(),
+            metadata: json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_upsert_reports_every_item_in_input_order() {
+        let (_dir, rag) = pipeline("fake", 8).await;
+        rag.memory_upsert(None, "ns", "b".into(), "beta".into(), json!({}))
+            .await
+            .unwrap();
+
+        let statuses = rag
+            .memory_upsert_batch(
+                None,
+                vec![
+                    item("ns", Some("a"), "alpha"),
+                    item("ns", Some("c"), "  "),
+                    item("ns", Some("b"), "beta again"),
+                    item("ns", Some("a"), "alpha again"),
+                    item("ns", None, "no id"),
+                ],
+            )
+            .await
+            .unwrap();
+        let summary = statuses
+            .iter()
+            .map(|s| (s.id.as_str(), s.status))
+            .collect::<Vec<_>>();
+        let generated = content_hash("no id".as_bytes());
+        assert_eq!(
+            summary,
+            vec![
+                ("a", "superseded"),
+                ("c", "error"),
+                ("b", "updated"),
+                ("a", "inserted"),
+                (generated.as_str(), "inserted"),
+            ]
+        );
+        assert_eq!(statuses[1].error.as_deref(), Some("text is required"));
+
+        let a = rag.memory_get(None, "ns", "a").await.unwrap().unwrap();
+        assert_eq!(a.text, "alpha again");
+        assert!(rag.memory_get(None, "ns", "c").await.unwrap().is_none());
+        assert!(rag
+            .memory_get(None, "ns", &generated)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn a_rejected_batch_writes_nothing() {
+        let (_dir, rag) = pipeline("small", 8).await;
+        rag.memory_upsert(None, "ns", "a".into(), "alpha".into(), json!({}))
+            .await
+            .unwrap();
+        let other = RAGPipeline::new(Arc::new(FakeEmbedder::new("wide", 16)), rag.storage.clone());
+
+        assert!(other
+            .memory_upsert_batch(
+                None,
+                vec![
+                    item("ns", Some("b"), "beta"),
+                    item("ns", Some("c"), "gamma")
+                ],
+            )
+            .await
+            .is_err());
+        for id in ["b", "c"] {
+            assert!(rag.memory_get(None, "ns", id).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_delete_reports_every_key_in_input_order() {
+        let (_dir, rag) = pipeline("fake", 8).await;
+        for (ns, id) in [("ns", "a"), ("other", "b")] {
+            rag.memory_upsert(None, ns, id.into(), format!("text of {}", id), json!({}))
+                .await
+                .unwrap();
+        }
+
+        let key = |ns: &str, id: &str| (ns.to_string(), id.to_string());
+        let statuses = rag
+            .memory_delete_batch(
+                None,
+                vec![key("other", "b"), key("ns", "missing"), key("ns", "a")],
+            )
+            .await
+            .unwrap();
+        let summary = statuses
+            .iter()
+            .map(|s| (s.namespace.as_str(), s.id.as_str(), s.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("other", "b", "deleted"),
+                ("ns", "missing", "not_found"),
+                ("ns", "a", "deleted"),
+            ]
+        );
+        assert!(rag.memory_get(None, "ns", "a").await.unwrap().is_none());
+    }
+
+    /// Ranks candidates in reverse of the order they arrive in.
+    struct Reversed;
+
+    #[async_trait]
+    impl Reranker for Reversed {
+        fn reranker_model(&self) -> &str {
+            "reversed"
+        }
+
+        async fn rerank(&self, _query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>> {
+            Ok((0..documents.len())
+                .rev()
+                .enumerate()
+                .map(|(rank, i)| (i, 1.0 - rank as f32 / 10.0))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn injected_providers_embed_and_rerank() {
+        let (_dir, storage) = temp_storage().await;
+        let storage = Arc::new(storage);
+        let embedder = Arc::new(FakeEmbedder::new("fake", 8));
+        let plain = RAGPipeline::new(embedder.clone(), storage.clone());
+        let reranked =
+            RAGPipeline::new(embedder.clone(), storage.clone()).with_reranker(Arc::new(Reversed));
+        for (id, text) in [("a", "aaaa"), ("b", "aaab"), ("c", "zzzz")] {
+            reranked
+                .memory_upsert(None, "ns", id.into(), text.into(), json!({}))
+                .await
+                .unwrap();
+        }
+        assert_eq!(embedder.embedded.load(Ordering::SeqCst), 3);
+
+        let ids =
+            |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+        let cosine = ids(plain.memory_search(None, "ns", "aaa", 10).await.unwrap());
+        assert_eq!(cosine, vec!["a", "b", "c"]);
+        let mut expected = cosine.clone();
+        expected.reverse();
+        let ranked = ids(reranked.memory_search(None, "ns", "aaa", 10).await.unwrap());
+        assert_eq!(ranked, expected);
+        // The query was embedded once and then served from the cache.
+        assert_eq!(embedder.embedded.load(Ordering::SeqCst), 4);
+
+        let status = reranked.embedder_status();
+        assert_eq!(status.provider, "custom");
+        assert_eq!(status.model, "fake");
+        assert_eq!(status.reranker.as_deref(), Some("reversed"));
+        assert!(plain.embedder_status().reranker.is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use lancedb::query::{ExecutableQuery, QueryBase};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+use super::{batch_to_docs, ChromaDocument, StorageManager};
+
+/// What a write does when its text is already stored under another id in the
+/// namespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupPolicy {
+    /// Store the duplicate as its own row.
+    #[default]
+    Allow,
+    /// Keep the existing row and drop the new one.
+    Skip,
+    /// Keep the existing row and merge the new metadata into it.
+    Merge,
+}
+
+impl FromStr for DedupPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "allow" => Ok(DedupPolicy::Allow),
+            "skip" => Ok(DedupPolicy::Skip),
+            "merge" => Ok(DedupPolicy::Merge),
+            other => Err(anyhow!(
+                "Unknown dedup policy '{}': use 'allow', 'skip' or 'merge'",
+                other
+            )),
+        }
+    }
+}
+
+/// Per-namespace behaviour, kept in sled.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamespaceSettings {
+    pub dedup: DedupPolicy,
+    /// Task description given to instruction-tuned embedding models with each query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+}
+
+/// Merge `incoming` metadata into `existing`: keys from `incoming` win, except that
+/// two arrays are combined without repeating elements.
+pub fn merge_metadata(existing: &mut Value, incoming: &Value) {
+    let Some(incoming) = incoming.as_object() else {
+        return;
+    };
+    if !existing.is_object() {
+        *existing = Value::Object(Default::default());
+    }
+    let Some(target) = existing.as_object_mut() else {
+        return;
+    };
+    for (key, value) in incoming {
+        match (target.get_mut(key), value) {
+            (Some(Value::Array(current)), Value::Array(added)) => {
+                for item in added {
+                    if !current.contains(item) {
+                        current.push(item.clone());
+                    }
+                }
+            }
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+impl StorageManager {
+    pub fn namespace_settings(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+    ) -> Result<NamespaceSettings> {
+        let key = self.settings_key(collection, namespace)?;
+        match self.namespaces.get(key)? {
+            Some(raw) => Ok(serde_json::from_slice(&raw)?),
+            None => Ok(NamespaceSettings::default()),
+        }
+    }
+
+    pub fn set_namespace_settings(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        settings: &NamespaceSettings,
+    ) -> Result<()> {
+        let key = self.settings_key(collection, namespace)?;
+        self.namespaces.insert(key, serde_json::to_vec(settings)?)?;
+        self.namespaces.flush()?;
+        Ok(())
+    }
+
+    /// Live rows of a namespace whose text hashes to one of `hashes`.
+    pub async fn find_by_content(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        hashes: &[String],
+    ) -> Result<Vec<ChromaDocument>> {
+        let collection = self.resolve_collection(collection)?;
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(Vec::new());
+        };
+        let quoted = hashes
+            .iter()
+            .map(|h| format!("'{}'", h.replace('\'', "''")))
+            .collect::<Vec<_>>();
+        let filter = format!(
+            "{} AND content_hash IN ({})",
+            self.live_namespace_filter(Some(namespace)),
+            quoted.join(", ")
+        );
+        let mut stream = table.query().only_if(filter).execute().await?;
+        let mut docs = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            docs.append(&mut batch_to_docs(&batch)?);
+        }
+        Ok(docs)
+    }
+
+    fn settings_key(&self, collection: Option<&str>, namespace: &str) -> Result<String> {
+        let collection = self.resolve_collection(collection)?;
+        Ok(format!("{}\0{}", collection, namespace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_overwrites_scalars_and_unions_arrays() {
+        let mut existing = json!({"source": "a", "tags": ["x", "y"], "keep": 1});
+        merge_metadata(&mut existing, &json!({"source": "b", "tags": ["y", "z"]}));
+        assert_eq!(
+            existing,
+            json!({"source": "b", "tags": ["x", "y", "z"], "keep": 1})
+        );
+
+        let mut empty = Value::Null;
+        merge_metadata(&mut empty, &json!({"k": true}));
+        assert_eq!(empty, json!({"k": true}));
+    }
+
+    #[test]
+    fn policy_parses_case_insensitively() {
+        assert_eq!("Skip".parse::<DedupPolicy>().unwrap(), DedupPolicy::Skip);
+        assert!("drop".parse::<DedupPolicy>().is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::StorageManager;
+
+/// Default size cap of the persistent embedding cache.
+pub const DEFAULT_EMBEDDING_CACHE_MB: usize = 1024;
+
+/// Prefix of embedding entries in the in-memory cache shared with [`StorageManager::get`].
+const MEMORY_PREFIX: &str = "embedding\0";
+
+/// Eviction frees space down to this share of the cap, so it does not run on every write.
+const EVICT_TO: f64 = 0.9;
+
+/// Distinct hit entries collected before their LRU positions are written in one batch.
+const HIT_BATCH: usize = 256;
+
+/// Running totals of the embedding cache. Sizes are restored from sled on startup;
+/// hit and miss counts start at zero.
+#[derive(Debug, Default)]
+pub(super) struct EmbeddingCacheCounters {
+    entries: AtomicU64,
+    bytes: AtomicU64,
+    memory_hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl EmbeddingCacheCounters {
+    /// Totals for the entries already in `lru`.
+    pub(super) fn load(lru: &sled::Tree) -> Result<Self> {
+        let counters = Self::default();
+        for entry in lru.iter() {
+            let (_, value) = entry?;
+            let (size, _) = decode_lru(&value)?;
+            counters.entries.fetch_add(1, Ordering::Relaxed);
+            counters.bytes.fetch_add(size, Ordering::Relaxed);
+        }
+        Ok(counters)
+    }
+}
+
+/// Hit rates and size of the embedding cache, as returned by `embedding_cache_stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingCacheStats {
+    pub entries: u64,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    /// Lookups answered from memory and from sled since startup.
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub evictions: u64,
+}
+
+impl StorageManager {
+    /// Cached vectors of `model` for texts with the given content hashes, in order.
+    pub async fn cached_embeddings(
+        &self,
+        model: &str,
+        hashes: &[String],
+    ) -> Result<Vec<Option<Vec<f32>>>> {
+        if self.embedding_cache_max == 0 {
+            return Ok(vec![None; hashes.len()]);
+        }
+        let counters = &self.embedding_counters;
+        let mut found = Vec::with_capacity(hashes.len());
+        let mut hits = Vec::new();
+        for hash in hashes {
+            let key = embedding_key(model, hash);
+            let memory_key = format!("{}{}", MEMORY_PREFIX, key);
+            if let Some(bytes) = self.cache.get(&memory_key).await {
+                counters.memory_hits.fetch_add(1, Ordering::Relaxed);
+                found.push(Some(decode_vector(&bytes)?));
+                hits.push(key);
+                continue;
+            }
+            match self.embeddings.get(&key)? {
+                Some(raw) => {
+                    counters.disk_hits.fetch_add(1, Ordering::Relaxed);
+                    let (_, bytes) = split_entry(&raw)?;
+                    self.cache.insert(memory_key, bytes.to_vec()).await;
+                    found.push(Some(decode_vector(bytes)?));
+                    hits.push(key);
+                }
+                None => {
+                    counters.misses.fetch_add(1, Ordering::Relaxed);
+                    found.push(None);
+                }
+            }
+        }
+        self.record_embedding_hits(hits)?;
+        Ok(found)
+    }
+
+    /// Remember `vectors[i]` as the embedding of the text hashed to `hashes[i]`, then
+    /// evict the least recently used entries if the cache outgrew its cap.
+    pub async fn cache_embeddings(
+        &self,
+        model: &str,
+        hashes: &[String],
+        vectors: &[Vec<f32>],
+    ) -> Result<()> {
+        if self.embedding_cache_max == 0 {
+            return Ok(());
+        }
+        let counters = &self.embedding_counters;
+        for (hash, vector) in hashes.iter().zip(vectors) {
+            let key = embedding_key(model, hash);
+            let bytes = encode_vector(vector);
+            let seq = self.db.generate_id()?;
+            let mut value = seq.to_be_bytes().to_vec();
+            value.extend_from_slice(&bytes);
+            let size = (key.len() + value.len()) as u64;
+
+            let previous = self.embeddings.insert(&key, value)?;
+            match previous {
+                Some(old) => {
+                    let (old_seq, _) = split_entry(&old)?;
+                    self.embedding_lru.remove(old_seq.to_be_bytes())?;
+                    counters
+                        .bytes
+                        .fetch_sub((key.len() + old.len()) as u64, Ordering::Relaxed);
+                }
+                None => {
+                    counters.entries.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            self.embedding_lru
+                .insert(seq.to_be_bytes(), encode_lru(size, &key))?;
+            counters.bytes.fetch_add(size, Ordering::Relaxed);
+            self.cache
+                .insert(format!("{}{}", MEMORY_PREFIX, key), bytes)
+                .await;
+        }
+        if counters.bytes.load(Ordering::Relaxed) > self.embedding_cache_max {
+            self.evict_embeddings().await?;
+        }
+        Ok(())
+    }
+
+    pub fn embedding_cache_stats(&self) -> EmbeddingCacheStats {
+        let counters = &self.embedding_counters;
+        let memory_hits = counters.memory_hits.load(Ordering::Relaxed);
+        let disk_hits = counters.disk_hits.load(Ordering::Relaxed);
+        let misses = counters.misses.load(Ordering::Relaxed);
+        let lookups = memory_hits + disk_hits + misses;
+        EmbeddingCacheStats {
+            entries: counters.entries.load(Ordering::Relaxed),
+            bytes: counters.bytes.load(Ordering::Relaxed),
+            max_bytes: self.embedding_cache_max,
+            memory_hits,
+            disk_hits,
+            misses,
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                (memory_hits + disk_hits) as f64 / lookups as f64
+            },
+            evictions: counters.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drop every cached embedding; returns how many there were.
+    pub async fn clear_embedding_cache(&self) -> Result<u64> {
+        let entries = self.embedding_counters.entries.swap(0, Ordering::Relaxed);
+        self.hits().clear();
+        self.embeddings.clear()?;
+        self.embedding_lru.clear()?;
+        self.embedding_counters.bytes.store(0, Ordering::Relaxed);
+        self.cache
+            .invalidate_entries_if(|key, _| key.starts_with(MEMORY_PREFIX))
+            .map_err(|e| anyhow!("Cannot clear the in-memory embedding cache: {}", e))?;
+        Ok(entries)
+    }
+
+    fn hits(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.embedding_hits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remember that entries were used, whether they came from memory or sled. Their LRU
+    /// positions are written once enough distinct entries were hit, or before eviction.
+    fn record_embedding_hits(&self, keys: Vec<String>) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let pending = {
+            let mut hits = self.hits();
+            hits.extend(keys);
+            hits.len()
+        };
+        if pending >= HIT_BATCH {
+            self.flush_embedding_hits()?;
+        }
+        Ok(())
+    }
+
+    /// Give every entry hit since the last flush a new position at the recent end of the
+    /// LRU order, with one batch per tree.
+    fn flush_embedding_hits(&self) -> Result<()> {
+        let keys = std::mem::take(&mut *self.hits());
+        let mut entries = sled::Batch::default();
+        let mut lru = sled::Batch::default();
+        for key in keys {
+            // Evicted or cleared since the hit.
+            let Some(raw) = self.embeddings.get(&key)? else {
+                continue;
+            };
+            let (seq, bytes) = split_entry(&raw)?;
+            let next = self.db.generate_id()?;
+            let mut value = next.to_be_bytes().to_vec();
+            value.extend_from_slice(bytes);
+            let size = (key.len() + value.len()) as u64;
+            entries.insert(key.as_bytes(), value);
+            lru.remove(&seq.to_be_bytes()[..]);
+            lru.insert(&next.to_be_bytes()[..], encode_lru(size, &key));
+        }
+        self.embeddings.apply_batch(entries)?;
+        self.embedding_lru.apply_batch(lru)?;
+        Ok(())
+    }
+
+    async fn evict_embeddings(&self) -> Result<()> {
+        self.flush_embedding_hits()?;
+        let counters = &self.embedding_counters;
+        let target = (self.embedding_cache_max as f64 * EVICT_TO) as u64;
+        let mut evicted = 0u64;
+        while counters.bytes.load(Ordering::Relaxed) > target {
+            let Some((seq, lru)) = self.embedding_lru.pop_min()? else {
+                break;
+            };
+            let (size, key) = decode_lru(&lru)?;
+            // Only remove the entry if this LRU slot is still its current one.
+            let current = match self.embeddings.get(key)? {
+                Some(raw) => split_entry(&raw)?.0.to_be_bytes() == *seq,
+                None => false,
+            };
+            if current {
+                self.embeddings.remove(key)?;
+                self.cache
+                    .invalidate(&format!("{}{}", MEMORY_PREFIX, key))
+                    .await;
+                counters.entries.fetch_sub(1, Ordering::Relaxed);
+                counters.bytes.fetch_sub(size, Ordering::Relaxed);
+                evicted += 1;
+            }
+        }
+        counters.evictions.fetch_add(evicted, Ordering::Relaxed);
+        tracing::debug!("Evicted {} cached embeddings", evicted);
+        Ok(())
+    }
+}
+
+fn embedding_key(model: &str, hash: &str) -> String {
+    format!("{}\0{}", model, hash)
+}
+
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Result<Vec<f32>> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(anyhow!("Corrupt cached embedding of {} bytes", bytes.len()));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Split a stored entry into its LRU sequence number and vector bytes.
+fn split_entry(raw: &[u8]) -> Result<(u64, &[u8])> {
+    if raw.len() < 8 {
+        return Err(anyhow!("Corrupt embedding cache entry"));
+    }
+    let (seq, bytes) = raw.split_at(8);
+    Ok((u64::from_be_bytes(seq.try_into()?), bytes))
+}
+
+/// LRU slots record the entry's size and key.
+fn encode_lru(size: u64, key: &str) -> Vec<u8> {
+    let mut value = size.to_be_bytes().to_vec();
+    value.extend_from_slice(key.as_bytes());
+    value
+}
+
+fn decode_lru(raw: &[u8]) -> Result<(u64, &str)> {
+    let (size, key) = split_entry(raw)?;
+    Ok((size, std::str::from_utf8(key)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::temp_storage;
+
+    #[tokio::test]
+    async fn entries_hit_from_memory_survive_eviction() {
+        let (_dir, storage) = temp_storage().await;
+        // Entries of 4-dim vectors take 28 bytes: three fit, a fourth evicts one.
+        let storage = storage.with_embedding_cache_limit(100);
+        let hashes = ["h0", "h1", "h2", "h3"].map(String::from);
+        for (i, hash) in hashes[..3].iter().enumerate() {
+            storage
+                .cache_embeddings("m", std::slice::from_ref(hash), &[vec![i as f32; 4]])
+                .await
+                .unwrap();
+        }
+
+        // The oldest entry is read back from memory, which makes it the newest.
+        let hit = storage.cached_embeddings("m", &hashes[..1]).await.unwrap();
+        assert_eq!(hit, vec![Some(vec![0.0; 4])]);
+        assert_eq!(storage.embedding_cache_stats().memory_hits, 1);
+
+        storage
+            .cache_embeddings("m", &hashes[3..], &[vec![3.0; 4]])
+            .await
+            .unwrap();
+        let stats = storage.embedding_cache_stats();
+        assert_eq!((stats.entries, stats.evictions), (3, 1));
+        let found = storage.cached_embeddings("m", &hashes).await.unwrap();
+        let kept = found.iter().map(Option::is_some).collect::<Vec<_>>();
+        assert_eq!(kept, vec![true, false, true, true]);
+    }
+
+    #[test]
+    fn vectors_and_lru_slots_round_trip() {
+        let vector = vec![0.5, -1.25, f32::MAX];
+        assert_eq!(decode_vector(&encode_vector(&vector)).unwrap(), vector);
+        assert!(decode_vector(&[0, 1, 2]).is_err());
+
+        let slot = encode_lru(42, "model\0abc");
+        assert_eq!(decode_lru(&slot).unwrap(), (42, "model\0abc"));
+        assert!(split_entry(&[1, 2]).is_err());
+    }
+}
@@ -0,0 +1,627 @@
+use anyhow::{anyhow, Context, Result};
+use arrow_schema::SchemaRef;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use parquet::arrow::{AsyncArrowWriter, ParquetRecordBatchStreamBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use super::{batch_to_docs, docs_to_record_batch, table_schema, ChromaDocument, EmbeddingSpec};
+
+/// Version tag written into every export; imports refuse anything else.
+pub const EXPORT_FORMAT_VERSION: &str = "mcp_memex.export.v1";
+
+/// Parquet schema metadata key holding the serialized [`ExportHeader`].
+const PARQUET_HEADER_KEY: &str = "mcp_memex.export";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Jsonl,
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Use the explicit format if given, otherwise infer it from the file extension
+    /// (`.parquet` means Parquet, anything else JSONL).
+    pub fn resolve(format: Option<&str>, path: &Path) -> Result<Self> {
+        if let Some(format) = format {
+            return format.parse();
+        }
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        Ok(if ext == "parquet" {
+            ExportFormat::Parquet
+        } else {
+            ExportFormat::Jsonl
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => Err(anyhow!(
+                "Unknown export format '{}': use 'jsonl' or 'parquet'",
+                other
+            )),
+        }
+    }
+}
+
+/// Describes where an export came from. It is the first line of a JSONL export and
+/// schema metadata in a Parquet export.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportHeader {
+    pub format: String,
+    pub collection: String,
+    pub namespace: String,
+    pub model: String,
+    pub dim: usize,
+    pub exported_at: i64,
+}
+
+/// One JSONL row.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportRow {
+    id: String,
+    namespace: String,
+    text: String,
+    #[serde(default)]
+    metadata: Value,
+    vector: Vec<f32>,
+    #[serde(default)]
+    updated_at: Option<i64>,
+}
+
+impl From<ChromaDocument> for ExportRow {
+    fn from(doc: ChromaDocument) -> Self {
+        Self {
+            id: doc.id,
+            namespace: doc.namespace,
+            text: doc.document,
+            metadata: doc.metadata,
+            vector: doc.embedding,
+            updated_at: doc.updated_at,
+        }
+    }
+}
+
+impl From<ExportRow> for ChromaDocument {
+    fn from(row: ExportRow) -> Self {
+        Self {
+            id: row.id,
+            namespace: row.namespace,
+            embedding: row.vector,
+            metadata: row.metadata,
+            document: row.text,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Write every batch of `documents` to a new export file and return the row count.
+/// If anything fails after the file was created it is removed again, so a retry can
+/// use the same name.
+pub(super) async fn write_export(
+    path: &Path,
+    format: ExportFormat,
+    header: &ExportHeader,
+    mut documents: BoxStream<'_, Result<Vec<ChromaDocument>>>,
+) -> Result<usize> {
+    let mut writer = ExportWriter::create(path, format, header).await?;
+    let written = async {
+        let mut rows = 0;
+        while let Some(docs) = documents.try_next().await? {
+            rows += docs.len();
+            writer.write(docs).await?;
+        }
+        writer.finish().await?;
+        Ok(rows)
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    written
+}
+
+enum ExportWriter {
+    Jsonl(BufWriter<File>),
+    Parquet {
+        writer: Box<AsyncArrowWriter<File>>,
+        schema: SchemaRef,
+        dim: usize,
+    },
+}
+
+impl ExportWriter {
+    async fn create(path: &Path, format: ExportFormat, header: &ExportHeader) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Never replace an existing file.
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await
+            .with_context(|| format!("Cannot create export file {}", path.display()))?;
+        let writer = Self::start(file, format, header).await;
+        if writer.is_err() {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        writer
+    }
+
+    async fn start(file: File, format: ExportFormat, header: &ExportHeader) -> Result<Self> {
+        match format {
+            ExportFormat::Jsonl => {
+                let mut out = BufWriter::new(file);
+                out.write_all(serde_json::to_string(header)?.as_bytes())
+                    .await?;
+                out.write_all(b"\n").await?;
+                Ok(ExportWriter::Jsonl(out))
+            }
+            ExportFormat::Parquet => {
+                let spec = EmbeddingSpec::new(header.model.clone(), header.dim);
+                let mut metadata = table_schema(&spec).metadata().clone();
+                metadata.insert(
+                    PARQUET_HEADER_KEY.to_string(),
+                    serde_json::to_string(header)?,
+                );
+                let schema = Arc::new(table_schema(&spec).as_ref().clone().with_metadata(metadata));
+                let writer = Box::new(AsyncArrowWriter::try_new(file, schema.clone(), None)?);
+                Ok(ExportWriter::Parquet {
+                    writer,
+                    schema,
+                    dim: header.dim,
+                })
+            }
+        }
+    }
+
+    async fn write(&mut self, documents: Vec<ChromaDocument>) -> Result<()> {
+        match self {
+            ExportWriter::Jsonl(out) => {
+                for doc in documents {
+                    let line = serde_json::to_string(&ExportRow::from(doc))?;
+                    out.write_all(line.as_bytes()).await?;
+                    out.write_all(b"\n").await?;
+                }
+            }
+            ExportWriter::Parquet {
+                writer,
+                schema,
+                dim,
+            } => {
+                if documents.is_empty() {
+                    return Ok(());
+                }
+                let batch = docs_to_record_batch(&documents, *dim, None)?;
+                writer.write(&batch.with_schema(schema.clone())?).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<()> {
+        match self {
+            ExportWriter::Jsonl(mut out) => out.flush().await?,
+            ExportWriter::Parquet { writer, .. } => {
+                writer.close().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A caller-supplied path that names a file relative to a directory without leaving
+/// it lexically; `kind` names the path in errors.
+fn relative_file<'a>(kind: &str, path: &'a str) -> Result<(&'a Path, &'a std::ffi::OsStr)> {
+    let relative = Path::new(path);
+    let file_name = relative
+        .file_name()
+        .ok_or_else(|| anyhow!("{} path '{}' does not name a file", kind, path))?;
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(anyhow!(
+            "{} path '{}' must be relative to the export directory and stay inside it",
+            kind,
+            path
+        ));
+    }
+    Ok((relative, file_name))
+}
+
+/// Place a caller-supplied export path inside `dir`. Only relative paths that stay
+/// below `dir` are accepted, also after following symlinks; missing directories are
+/// created.
+pub async fn export_path_in(dir: &Path, path: &str) -> Result<PathBuf> {
+    let (relative, file_name) = relative_file("Export", path)?;
+    let parent = dir.join(relative.parent().unwrap_or(Path::new("")));
+    tokio::fs::create_dir_all(&parent)
+        .await
+        .with_context(|| format!("Cannot create export directory {}", parent.display()))?;
+    let root = tokio::fs::canonicalize(dir).await?;
+    let parent = tokio::fs::canonicalize(&parent).await?;
+    if !parent.starts_with(&root) {
+        return Err(anyhow!(
+            "Export path '{}' leaves the export directory {}",
+            path,
+            root.display()
+        ));
+    }
+    Ok(parent.join(file_name))
+}
+
+/// Find a caller-supplied import path inside `dir`, with the same rules as
+/// [`export_path_in`]; the file itself may not be a symlink out of `dir` either.
+pub async fn import_path_in(dir: &Path, path: &str) -> Result<PathBuf> {
+    let (relative, _) = relative_file("Import", path)?;
+    let root = tokio::fs::canonicalize(dir)
+        .await
+        .with_context(|| format!("Cannot open export directory {}", dir.display()))?;
+    let file = tokio::fs::canonicalize(dir.join(relative))
+        .await
+        .with_context(|| format!("Cannot open import file '{}' in {}", path, root.display()))?;
+    if !file.starts_with(&root) {
+        return Err(anyhow!(
+            "Import path '{}' leaves the export directory {}",
+            path,
+            root.display()
+        ));
+    }
+    Ok(file)
+}
+
+/// Read an export file, checking its header and that every vector matches the
+/// declared dimension.
+pub async fn read_export(
+    path: &Path,
+    format: ExportFormat,
+) -> Result<(ExportHeader, Vec<ChromaDocument>)> {
+    let file = File::open(path)
+        .await
+        .with_context(|| format!("Cannot open export file {}", path.display()))?;
+    let (header, documents) = match format {
+        ExportFormat::Jsonl => {
+            let mut lines = BufReader::new(file).lines();
+            let first = lines
+                .next_line()
+                .await?
+                .ok_or_else(|| anyhow!("Export file {} is empty", path.display()))?;
+            let header: ExportHeader =
+                serde_json::from_str(&first).context("Invalid export header line")?;
+            let mut documents = Vec::new();
+            let mut line_no = 1;
+            while let Some(line) = lines.next_line().await? {
+                line_no += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let row: ExportRow = serde_json::from_str(&line)
+                    .with_context(|| format!("Invalid export row on line {}", line_no))?;
+                documents.push(ChromaDocument::from(row));
+            }
+            (header, documents)
+        }
+        ExportFormat::Parquet => {
+            let builder = ParquetRecordBatchStreamBuilder::new(file).await?;
+            let raw = builder
+                .schema()
+                .metadata()
+                .get(PARQUET_HEADER_KEY)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} is not an mcp_memex Parquet export", path.display()))?;
+            let header: ExportHeader =
+                serde_json::from_str(&raw).context("Invalid export header")?;
+            let mut stream = builder.build()?;
+            let mut documents = Vec::new();
+            while let Some(batch) = stream.try_next().await? {
+                documents.append(&mut batch_to_docs(&batch)?);
+            }
+            (header, documents)
+        }
+    };
+
+    if header.format != EXPORT_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Unsupported export format '{}' (expected '{}')",
+            header.format,
+            EXPORT_FORMAT_VERSION
+        ));
+    }
+    if let Some(doc) = documents.iter().find(|d| d.embedding.len() != header.dim) {
+        return Err(anyhow!(
+            "Row '{}' has a {}-dim vector but the export declares {} dims",
+            doc.id,
+            doc.embedding.len(),
+            header.dim
+        ));
+    }
+    Ok((header, documents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::mock::FakeEmbedder;
+    use crate::rag::RAGPipeline;
+    use crate::storage::mock::{doc, temp_storage};
+    use crate::storage::StorageManager;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    /// A pipeline over a fresh store, with the store kept for inspection.
+    async fn pipeline(
+        model: &'static str,
+        dim: usize,
+    ) -> (tempfile::TempDir, Arc<StorageManager>, RAGPipeline) {
+        let (dir, storage) = temp_storage().await;
+        let storage = Arc::new(storage);
+        let rag = RAGPipeline::new(Arc::new(FakeEmbedder::new(model, dim)), storage.clone());
+        (dir, storage, rag)
+    }
+
+    async fn rows(storage: &StorageManager) -> Vec<ChromaDocument> {
+        let mut rows = storage
+            .stream_all_documents(None)
+            .await
+            .unwrap()
+            .try_concat()
+            .await
+            .unwrap();
+        rows.sort_by(|a, b| a.id.cmp(&b.id));
+        rows
+    }
+
+    /// Export a namespace in `format` and import it into an empty store; every column
+    /// must come back unchanged.
+    async fn round_trip(format: ExportFormat, path: &Path) {
+        let (_dir, source, rag) = pipeline("small", 4).await;
+        rag.memory_upsert(
+            None,
+            "ns",
+            "a".into(),
+            "alpha".into(),
+            json!({"tags": ["x"], "n": 1}),
+        )
+        .await
+        .unwrap();
+        rag.memory_upsert(None, "ns", "b".into(), "beta".into(), json!({}))
+            .await
+            .unwrap();
+        rag.memory_upsert(None, "other", "c".into(), "gamma".into(), json!({}))
+            .await
+            .unwrap();
+
+        let report = rag
+            .export_namespace(None, "ns", path, format)
+            .await
+            .unwrap();
+        assert_eq!((report.rows, report.model.as_str()), (2, "small"));
+
+        let (_target_dir, target, imported) = pipeline("small", 4).await;
+        let report = imported
+            .import_namespace(None, path, format, None, false)
+            .await
+            .unwrap();
+        assert_eq!((report.rows, report.namespace.as_str()), (2, "ns"));
+
+        let expected = rows(&source)
+            .await
+            .into_iter()
+            .filter(|d| d.namespace == "ns")
+            .collect::<Vec<_>>();
+        let actual = rows(&target).await;
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual.id, expected.id);
+            assert_eq!(actual.namespace, expected.namespace);
+            assert_eq!(actual.document, expected.document);
+            assert_eq!(actual.metadata, expected.metadata);
+            assert_eq!(actual.embedding, expected.embedding);
+            assert!(expected.updated_at.is_some());
+            assert_eq!(actual.updated_at, expected.updated_at);
+        }
+
+        // A collection of another model refuses the vectors unless they are re-embedded.
+        let (_wide_dir, wide, rag) = pipeline("wide", 8).await;
+        rag.memory_upsert(None, "ns", "z".into(), "zeta".into(), json!({}))
+            .await
+            .unwrap();
+        let err = rag
+            .import_namespace(None, path, format, None, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("reembed"), "{}", err);
+
+        let report = rag
+            .import_namespace(None, path, format, Some("copy"), true)
+            .await
+            .unwrap();
+        assert!(report.reembedded);
+        assert_eq!(report.source_model, "small");
+        assert_eq!((report.model.as_str(), report.dim), ("wide", 8));
+        let copies = rows(&wide)
+            .await
+            .into_iter()
+            .filter(|d| d.namespace == "copy")
+            .collect::<Vec<_>>();
+        assert_eq!(copies.len(), 2);
+        assert!(copies.iter().all(|d| d.embedding.len() == 8));
+        assert_eq!(
+            wide.collection_embedding(None).await.unwrap(),
+            Some(EmbeddingSpec::new("wide", 8))
+        );
+    }
+
+    #[tokio::test]
+    async fn jsonl_exports_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ns.jsonl");
+        round_trip(ExportFormat::Jsonl, &path).await;
+
+        // Rows whose vectors disagree with the header are rejected.
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+        let mut row: Value = serde_json::from_str(&lines[1]).unwrap();
+        row["vector"] = json!([1.0, 2.0]);
+        lines[1] = row.to_string();
+        let tampered = path.with_file_name("tampered.jsonl");
+        std::fs::write(&tampered, lines.join("\n")).unwrap();
+        let err = read_export(&tampered, ExportFormat::Jsonl)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("2-dim vector"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn parquet_exports_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        round_trip(ExportFormat::Parquet, &dir.path().join("ns.parquet")).await;
+    }
+
+    #[tokio::test]
+    async fn failed_exports_leave_no_file_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.parquet");
+        let header = ExportHeader {
+            format: EXPORT_FORMAT_VERSION.to_string(),
+            collection: "docs".to_string(),
+            namespace: "ns".to_string(),
+            model: "small".to_string(),
+            dim: 4,
+            exported_at: 0,
+        };
+        let batches = |fail: bool| {
+            let mut batches = vec![Ok(vec![doc("ns", "a", "alpha", vec![1.0; 4])])];
+            if fail {
+                batches.push(Err(anyhow!("stream broke")));
+            }
+            futures::stream::iter(batches).boxed()
+        };
+
+        let err = write_export(&path, ExportFormat::Parquet, &header, batches(true))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("stream broke"));
+        assert!(!path.exists());
+
+        let rows = write_export(&path, ExportFormat::Parquet, &header, batches(false))
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        let (_, documents) = read_export(&path, ExportFormat::Parquet).await.unwrap();
+        assert_eq!(documents.len(), 1);
+    }
+
+    #[test]
+    fn format_is_resolved_from_name_or_extension() {
+        let parquet = Path::new("/tmp/notes.parquet");
+        let jsonl = Path::new("/tmp/notes.jsonl");
+        assert_eq!(
+            ExportFormat::resolve(None, parquet).unwrap(),
+            ExportFormat::Parquet
+        );
+        assert_eq!(
+            ExportFormat::resolve(None, jsonl).unwrap(),
+            ExportFormat::Jsonl
+        );
+        assert_eq!(
+            ExportFormat::resolve(Some("PARQUET"), jsonl).unwrap(),
+            ExportFormat::Parquet
+        );
+        assert!(ExportFormat::resolve(Some("csv"), jsonl).is_err());
+    }
+
+    #[tokio::test]
+    async fn export_paths_stay_in_the_export_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+
+        let path = export_path_in(dir.path(), "team/notes.jsonl")
+            .await
+            .unwrap();
+        assert_eq!(path, root.join("team").join("notes.jsonl"));
+        assert!(root.join("team").is_dir());
+
+        for bad in [
+            "/etc/passwd",
+            "../notes.jsonl",
+            "team/../../x.jsonl",
+            "./x",
+            "",
+        ] {
+            assert!(export_path_in(dir.path(), bad).await.is_err(), "{}", bad);
+        }
+
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().unwrap();
+            std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+            assert!(export_path_in(dir.path(), "link/x.jsonl").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn import_paths_stay_in_the_export_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(dir.path().join("team")).unwrap();
+        std::fs::write(dir.path().join("team/notes.jsonl"), "").unwrap();
+
+        let path = import_path_in(dir.path(), "team/notes.jsonl")
+            .await
+            .unwrap();
+        assert_eq!(path, root.join("team").join("notes.jsonl"));
+
+        for bad in ["/etc/passwd", "../notes.jsonl", "team/missing.jsonl", ""] {
+            assert!(import_path_in(dir.path(), bad).await.is_err(), "{}", bad);
+        }
+
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().unwrap();
+            let secret = outside.path().join("secret.jsonl");
+            std::fs::write(&secret, "").unwrap();
+            std::os::unix::fs::symlink(&secret, dir.path().join("link.jsonl")).unwrap();
+            assert!(import_path_in(dir.path(), "link.jsonl").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_never_overwrite_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.jsonl");
+        std::fs::write(&path, "keep me").unwrap();
+        let header = ExportHeader {
+            format: EXPORT_FORMAT_VERSION.to_string(),
+            collection: "docs".to_string(),
+            namespace: "ns".to_string(),
+            model: "small".to_string(),
+            dim: 4,
+            exported_at: 0,
+        };
+
+        assert!(ExportWriter::create(&path, ExportFormat::Jsonl, &header)
+            .await
+            .is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::Bound;
+use std::time::Duration;
+
+use super::{now_millis, StorageManager};
+
+/// Most keys returned by one `kv_list` page.
+pub const KV_LIST_MAX: usize = 1000;
+
+/// A scratchpad value. `version` starts at 1 and grows by one on every write, so it
+/// can be passed back as `expected_version` for compare-and-swap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KvEntry {
+    pub key: String,
+    #[serde(skip_serializing_if = "Value::is_null", default)]
+    pub value: Value,
+    pub version: u64,
+    pub updated_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl KvEntry {
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KvPage {
+    pub entries: Vec<KvEntry>,
+    pub next_cursor: Option<String>,
+}
+
+// The scratchpad lives in its own sled tree instead of behind `get`/`set`: versions
+// are checked with sled's compare-and-swap and listings are range scans, neither of
+// which the moka cache in front of `get` can answer without going stale.
+impl StorageManager {
+    pub fn kv_get(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<KvEntry>> {
+        let k = self.kv_key(collection, namespace, key)?;
+        Ok(self.kv_live(&k)?.map(|(_, entry)| entry))
+    }
+
+    /// Write a value. With `expected_version` the write only happens if the key is at
+    /// that version (0 meaning absent), otherwise it fails without changing anything.
+    pub fn kv_set(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+        value: Value,
+        ttl: Option<Duration>,
+        expected_version: Option<u64>,
+    ) -> Result<KvEntry> {
+        let k = self.kv_key(collection, namespace, key)?;
+        loop {
+            let (raw, current) = match self.kv_live(&k)? {
+                Some((raw, entry)) => (Some(raw), Some(entry)),
+                None => (self.kv.get(&k)?, None),
+            };
+            let current_version = current.as_ref().map_or(0, |e| e.version);
+            check_version(key, expected_version, current_version)?;
+            let now = now_millis();
+            let entry = KvEntry {
+                key: key.to_string(),
+                value: value.clone(),
+                version: current_version + 1,
+                updated_at: now,
+                expires_at: ttl.map(|ttl| {
+                    now.saturating_add(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX))
+                }),
+            };
+            let bytes = serde_json::to_vec(&entry)?;
+            if self.kv.compare_and_swap(&k, raw, Some(bytes))?.is_ok() {
+                self.kv.flush()?;
+                return Ok(entry);
+            }
+        }
+    }
+
+    /// Remove a key, optionally only if it is at `expected_version`. Returns whether a
+    /// live value was removed.
+    pub fn kv_delete(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        key: &str,
+        expected_version: Option<u64>,
+    ) -> Result<bool> {
+        let k = self.kv_key(collection, namespace, key)?;
+        loop {
+            let Some((raw, entry)) = self.kv_live(&k)? else {
+                check_version(key, expected_version, 0)?;
+                return Ok(false);
+            };
+            check_version(key, expected_version, entry.version)?;
+            if self
+                .kv
+                .compare_and_swap(&k, Some(raw), None as Option<&[u8]>)?
+                .is_ok()
+            {
+                self.kv.flush()?;
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Live keys of a namespace in byte order, starting after `cursor`.
+    pub fn kv_list(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        prefix: Option<&str>,
+        limit: usize,
+        cursor: Option<&str>,
+        with_values: bool,
+    ) -> Result<KvPage> {
+        let base = self.kv_prefix(collection, namespace)?;
+        let scan = format!("{}{}", base, prefix.unwrap_or(""));
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(format!("{}{}", base, cursor)),
+            None => Bound::Included(scan.clone()),
+        };
+        let limit = limit.clamp(1, KV_LIST_MAX);
+        let now = now_millis();
+
+        let mut entries = Vec::new();
+        let mut next_cursor = None;
+        for item in self.kv.range::<String, _>((start, Bound::Unbounded)) {
+            let (k, raw) = item?;
+            if !k.starts_with(scan.as_bytes()) {
+                if k.as_ref() > scan.as_bytes() {
+                    break;
+                }
+                continue;
+            }
+            let mut entry: KvEntry = serde_json::from_slice(&raw)?;
+            if entry.expired(now) {
+                let _ = self
+                    .kv
+                    .compare_and_swap(&k, Some(raw), None as Option<&[u8]>)?;
+                continue;
+            }
+            if entries.len() == limit {
+                next_cursor = entries.last().map(|e: &KvEntry| e.key.clone());
+                break;
+            }
+            if !with_values {
+                entry.value = Value::Null;
+            }
+            entries.push(entry);
+        }
+        Ok(KvPage {
+            entries,
+            next_cursor,
+        })
+    }
+
+    /// Stored bytes and entry for a key, dropping it if it has expired.
+    fn kv_live(&self, k: &str) -> Result<Option<(sled::IVec, KvEntry)>> {
+        let Some(raw) = self.kv.get(k)? else {
+            return Ok(None);
+        };
+        let entry: KvEntry = serde_json::from_slice(&raw)?;
+        if entry.expired(now_millis()) {
+            let _ = self
+                .kv
+                .compare_and_swap(k, Some(raw), None as Option<&[u8]>)?;
+            return Ok(None);
+        }
+        Ok(Some((raw, entry)))
+    }
+
+    fn kv_key(&self, collection: Option<&str>, namespace: &str, key: &str) -> Result<String> {
+        if key.is_empty() || key.contains('\0') {
+            return Err(anyhow!("Key must be non-empty and must not contain NUL"));
+        }
+        Ok(format!("{}{}", self.kv_prefix(collection, namespace)?, key))
+    }
+
+    fn kv_prefix(&self, collection: Option<&str>, namespace: &str) -> Result<String> {
+        let collection = self.resolve_collection(collection)?;
+        if namespace.is_empty() || namespace.contains('\0') {
+            return Err(anyhow!(
+                "Namespace must be non-empty and must not contain NUL"
+            ));
+        }
+        Ok(format!("{}\0{}\0", collection, namespace))
+    }
+}
+
+fn check_version(key: &str, expected: Option<u64>, current: u64) -> Result<()> {
+    match expected {
+        Some(expected) if expected != current => Err(anyhow!(
+            "Version mismatch for '{}': expected {}, current {}",
+            key,
+            expected,
+            current
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::temp_storage;
+    use serde_json::json;
+
+    #[test]
+    fn version_check_treats_zero_as_absent() {
+        assert!(check_version("k", None, 3).is_ok());
+        assert!(check_version("k", Some(0), 0).is_ok());
+        assert!(check_version("k", Some(0), 1).is_err());
+        assert!(check_version("k", Some(2), 2).is_ok());
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_neither_read_nor_listed() {
+        let (_dir, storage) = temp_storage().await;
+        let ttl = Some(Duration::from_millis(20));
+        storage
+            .kv_set(None, "ns", "short", json!(1), ttl, None)
+            .unwrap();
+        storage
+            .kv_set(None, "ns", "long", json!(2), None, None)
+            .unwrap();
+        assert!(storage.kv_get(None, "ns", "short").unwrap().is_some());
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(storage.kv_get(None, "ns", "short").unwrap().is_none());
+        let page = storage.kv_list(None, "ns", None, 10, None, false).unwrap();
+        let keys = page
+            .entries
+            .iter()
+            .map(|e| e.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["long"]);
+
+        // An expired key counts as absent for compare-and-swap.
+        storage
+            .kv_set(None, "ns", "gone", json!(1), ttl, None)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let entry = storage
+            .kv_set(None, "ns", "gone", json!(2), None, Some(0))
+            .unwrap();
+        assert_eq!(entry.version, 1);
+    }
+
+    #[tokio::test]
+    async fn writes_with_a_stale_version_fail() {
+        let (_dir, storage) = temp_storage().await;
+        let first = storage
+            .kv_set(None, "ns", "k", json!("a"), None, Some(0))
+            .unwrap();
+        assert_eq!(first.version, 1);
+        let err = storage
+            .kv_set(None, "ns", "k", json!("b"), None, Some(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("expected 0, current 1"));
+
+        let second = storage
+            .kv_set(None, "ns", "k", json!("b"), None, Some(1))
+            .unwrap();
+        assert_eq!(second.version, 2);
+        assert!(storage
+            .kv_set(None, "ns", "k", json!("c"), None, Some(1))
+            .is_err());
+        let stored = storage.kv_get(None, "ns", "k").unwrap().unwrap();
+        assert_eq!((stored.value, stored.version), (json!("b"), 2));
+    }
+
+    #[tokio::test]
+    async fn deletes_check_the_expected_version() {
+        let (_dir, storage) = temp_storage().await;
+        storage
+            .kv_set(None, "ns", "k", json!(1), None, None)
+            .unwrap();
+        storage
+            .kv_set(None, "ns", "k", json!(2), None, None)
+            .unwrap();
+
+        assert!(storage.kv_delete(None, "ns", "k", Some(1)).is_err());
+        assert!(storage.kv_get(None, "ns", "k").unwrap().is_some());
+        assert!(storage.kv_delete(None, "ns", "k", Some(2)).unwrap());
+        assert!(storage.kv_get(None, "ns", "k").unwrap().is_none());
+        assert!(!storage.kv_delete(None, "ns", "k", None).unwrap());
+        assert!(storage.kv_delete(None, "ns", "k", Some(2)).is_err());
+    }
+
+    #[tokio::test]
+    async fn listings_filter_by_prefix_and_page_with_a_cursor() {
+        let (_dir, storage) = temp_storage().await;
+        for key in ["task/1", "task/2", "task/3", "task/4", "task/5", "note/1"] {
+            storage
+                .kv_set(None, "ns", key, json!(key), None, None)
+                .unwrap();
+        }
+        storage
+            .kv_set(None, "other", "task/9", json!(0), None, None)
+            .unwrap();
+
+        let mut keys = Vec::new();
+        let mut cursor = None;
+        let mut pages = 0;
+        loop {
+            let page = storage
+                .kv_list(None, "ns", Some("task/"), 2, cursor.as_deref(), true)
+                .unwrap();
+            pages += 1;
+            for entry in &page.entries {
+                assert_eq!(entry.value, json!(entry.key));
+                keys.push(entry.key.clone());
+            }
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(keys, ["task/1", "task/2", "task/3", "task/4", "task/5"]);
+        assert_eq!(pages, 3);
+
+        let page = storage.kv_list(None, "ns", None, 10, None, false).unwrap();
+        assert_eq!(page.entries.len(), 6);
+        assert!(page.entries.iter().all(|e| e.value.is_null()));
+    }
+}
@@ -0,0 +1,492 @@
+use anyhow::{anyhow, Result};
+use arrow_array::{Array, Int64Array};
+use futures::TryStreamExt;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::{format_millis, string_column, StorageManager, LIVE_FILTER};
+
+/// Characters of text returned per row in preview mode.
+pub const PREVIEW_CHARS: usize = 200;
+
+/// Most rows returned by one `memory_list` page.
+pub const LIST_MAX: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListSort {
+    #[default]
+    Id,
+    /// Last update time; rows without a timestamp sort as oldest.
+    Time,
+}
+
+impl FromStr for ListSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "id" => Ok(ListSort::Id),
+            "time" | "updated_at" => Ok(ListSort::Time),
+            other => Err(anyhow!("Unknown sort '{}': use 'id' or 'time'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub sort: ListSort,
+    pub descending: bool,
+    pub limit: usize,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Only rows whose metadata has these values (array fields match if they contain it).
+    pub metadata: Option<Map<String, Value>>,
+    /// Return only the first [`PREVIEW_CHARS`] characters of each text.
+    pub preview: bool,
+    /// List the namespace's trash instead of its live rows.
+    pub trashed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedDocument {
+    pub id: String,
+    pub namespace: String,
+    pub text: String,
+    pub metadata: Value,
+    pub updated_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentPage {
+    pub items: Vec<ListedDocument>,
+    pub next_cursor: Option<String>,
+}
+
+/// Position of a row in the listing order; the cursor is the key of the last row served.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    time: i64,
+    id: String,
+}
+
+impl SortKey {
+    fn new(sort: ListSort, updated_at: Option<i64>, id: &str) -> Self {
+        let time = match sort {
+            ListSort::Id => 0,
+            ListSort::Time => updated_at.unwrap_or(i64::MIN),
+        };
+        Self {
+            time,
+            id: id.to_string(),
+        }
+    }
+
+    fn encode(&self, sort: ListSort) -> String {
+        match sort {
+            ListSort::Id => format!("id:{}", self.id),
+            ListSort::Time => format!("time:{}:{}", self.time, self.id),
+        }
+    }
+
+    fn decode(sort: ListSort, cursor: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid cursor '{}' for sort {:?}", cursor, sort);
+        match sort {
+            ListSort::Id => {
+                let id = cursor.strip_prefix("id:").ok_or_else(invalid)?;
+                Ok(Self {
+                    time: 0,
+                    id: id.to_string(),
+                })
+            }
+            ListSort::Time => {
+                let rest = cursor.strip_prefix("time:").ok_or_else(invalid)?;
+                let (time, id) = rest.split_once(':').ok_or_else(invalid)?;
+                Ok(Self {
+                    time: time.parse().map_err(|_| invalid())?,
+                    id: id.to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// True when every key in `filter` is present in `metadata` with an equal value, or
+/// with an array value containing it.
+pub fn metadata_matches(metadata: &Value, filter: &Map<String, Value>) -> bool {
+    filter
+        .iter()
+        .all(|(key, expected)| match metadata.get(key) {
+            Some(Value::Array(items)) if !expected.is_array() => items.contains(expected),
+            Some(value) => value == expected,
+            None => false,
+        })
+}
+
+/// Row selection for bulk operations such as `memory_delete_where`.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentFilter {
+    pub namespace: Option<String>,
+    /// Exact `metadata.path`, as written by `rag_index`.
+    pub path: Option<String>,
+    /// Every tag must appear in `metadata.tags`.
+    pub tags: Vec<String>,
+    pub metadata: Option<Map<String, Value>>,
+    /// Unix ms bounds on `updated_at`; rows without a timestamp never match a bound.
+    pub updated_after: Option<i64>,
+    pub updated_before: Option<i64>,
+}
+
+impl DocumentFilter {
+    /// True when nothing but the namespace narrows the selection.
+    pub fn is_unbounded(&self) -> bool {
+        self.path.is_none()
+            && self.tags.is_empty()
+            && self.metadata.as_ref().is_none_or(|m| m.is_empty())
+            && self.updated_after.is_none()
+            && self.updated_before.is_none()
+    }
+
+    fn matches_metadata(&self, metadata: &Value) -> bool {
+        if let Some(path) = &self.path {
+            if metadata.get("path").and_then(Value::as_str) != Some(path.as_str()) {
+                return false;
+            }
+        }
+        let has_tag = |tag: &String| match metadata.get("tags") {
+            Some(Value::Array(tags)) => tags.iter().any(|t| t.as_str() == Some(tag)),
+            Some(Value::String(t)) => t == tag,
+            _ => false,
+        };
+        if !self.tags.iter().all(has_tag) {
+            return false;
+        }
+        self.metadata
+            .as_ref()
+            .is_none_or(|filter| metadata_matches(metadata, filter))
+    }
+}
+
+/// Parse an RFC 3339 timestamp, a `YYYY-MM-DD` date (midnight UTC) or Unix milliseconds.
+pub fn parse_timestamp(value: &Value) -> Result<i64> {
+    if let Some(ms) = value.as_i64() {
+        return Ok(ms);
+    }
+    let text = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected a date string or Unix milliseconds, got {}", value))?;
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Ok(t.timestamp_millis());
+    }
+    chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|t| t.and_utc().timestamp_millis())
+        .ok_or_else(|| anyhow!("Invalid date '{}': use RFC 3339 or YYYY-MM-DD", text))
+}
+
+impl StorageManager {
+    /// `(namespace, id)` of every row selected by `filter`. Namespace and time bounds are
+    /// pushed down to Lance; metadata is matched on the scalar columns only.
+    pub async fn matching_keys(
+        &self,
+        collection: Option<&str>,
+        filter: &DocumentFilter,
+    ) -> Result<Vec<(String, String)>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(Vec::new());
+        };
+        let mut predicates = vec![LIVE_FILTER.to_string()];
+        if let Some(ns) = &filter.namespace {
+            predicates.push(self.namespace_filter(ns));
+        }
+        if let Some(after) = filter.updated_after {
+            predicates.push(format!("updated_at >= {}", after));
+        }
+        if let Some(before) = filter.updated_before {
+            predicates.push(format!("updated_at < {}", before));
+        }
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec![
+                "namespace".to_string(),
+                "id".to_string(),
+                "metadata".to_string(),
+            ]))
+            .only_if(predicates.join(" AND "))
+            .execute()
+            .await?;
+        let mut keys = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let ns_col = string_column(&batch, "namespace")?;
+            let id_col = string_column(&batch, "id")?;
+            let metadata_col = string_column(&batch, "metadata")?;
+            for i in 0..batch.num_rows() {
+                let metadata: Value =
+                    serde_json::from_str(metadata_col.value(i)).unwrap_or_default();
+                if filter.matches_metadata(&metadata) {
+                    keys.push((ns_col.value(i).to_string(), id_col.value(i).to_string()));
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// One page of a namespace in `options.sort` order. Ordering and filtering read only
+    /// the scalar columns; text is fetched for the rows on the page.
+    pub async fn list_documents(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        options: &ListOptions,
+    ) -> Result<DocumentPage> {
+        let empty = DocumentPage {
+            items: Vec::new(),
+            next_cursor: None,
+        };
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(empty);
+        };
+        let after = options
+            .cursor
+            .as_deref()
+            .map(|c| SortKey::decode(options.sort, c))
+            .transpose()?;
+
+        let state = if options.trashed {
+            "deleted_at IS NOT NULL"
+        } else {
+            LIVE_FILTER
+        };
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec![
+                "id".to_string(),
+                "metadata".to_string(),
+                "updated_at".to_string(),
+                "deleted_at".to_string(),
+            ]))
+            .only_if(format!(
+                "{} AND {}",
+                self.namespace_filter(namespace),
+                state
+            ))
+            .execute()
+            .await?;
+        let mut rows = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let id_col = string_column(&batch, "id")?;
+            let metadata_col = string_column(&batch, "metadata")?;
+            let updated_col = batch
+                .column_by_name("updated_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            let deleted_col = batch
+                .column_by_name("deleted_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            for i in 0..batch.num_rows() {
+                let metadata: Value =
+                    serde_json::from_str(metadata_col.value(i)).unwrap_or_default();
+                if let Some(filter) = &options.metadata {
+                    if !metadata_matches(&metadata, filter) {
+                        continue;
+                    }
+                }
+                let updated_at = updated_col.filter(|c| c.is_valid(i)).map(|c| c.value(i));
+                let key = SortKey::new(options.sort, updated_at, id_col.value(i));
+                let past_cursor = match &after {
+                    None => true,
+                    Some(after) if options.descending => key < *after,
+                    Some(after) => key > *after,
+                };
+                if past_cursor {
+                    let deleted_at = deleted_col.filter(|c| c.is_valid(i)).map(|c| c.value(i));
+                    rows.push((key, metadata, updated_at, deleted_at));
+                }
+            }
+        }
+
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        if options.descending {
+            rows.reverse();
+        }
+        let limit = options.limit.clamp(1, LIST_MAX);
+        let next_cursor = (rows.len() > limit).then(|| rows[limit - 1].0.encode(options.sort));
+        rows.truncate(limit);
+        if rows.is_empty() {
+            return Ok(empty);
+        }
+
+        let texts = self
+            .texts_for(&table, namespace, rows.iter().map(|r| r.0.id.as_str()))
+            .await?;
+        let items = rows
+            .into_iter()
+            .map(|(key, metadata, updated_at, deleted_at)| {
+                let text = texts.get(&key.id).cloned().unwrap_or_default();
+                let (text, truncated) = if options.preview {
+                    preview(&text)
+                } else {
+                    (text, false)
+                };
+                ListedDocument {
+                    id: key.id,
+                    namespace: namespace.to_string(),
+                    text,
+                    metadata,
+                    updated_at: updated_at.and_then(format_millis),
+                    deleted_at: deleted_at.and_then(format_millis),
+                    truncated,
+                }
+            })
+            .collect();
+        Ok(DocumentPage { items, next_cursor })
+    }
+
+    /// Text of the given ids within a namespace.
+    async fn texts_for<'a>(
+        &self,
+        table: &lancedb::Table,
+        namespace: &str,
+        ids: impl Iterator<Item = &'a str>,
+    ) -> Result<HashMap<String, String>> {
+        let filter = format!(
+            "{} AND {}",
+            self.namespace_filter(namespace),
+            self.id_list_filter(ids)
+        );
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec!["id".to_string(), "text".to_string()]))
+            .only_if(filter.as_str())
+            .execute()
+            .await?;
+        let mut texts = HashMap::new();
+        while let Some(batch) = stream.try_next().await? {
+            let id_col = string_column(&batch, "id")?;
+            let text_col = string_column(&batch, "text")?;
+            for i in 0..batch.num_rows() {
+                texts.insert(id_col.value(i).to_string(), text_col.value(i).to_string());
+            }
+        }
+        Ok(texts)
+    }
+}
+
+fn preview(text: &str) -> (String, bool) {
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => (format!("{}…", &text[..end]), true),
+        None => (text.to_string(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::mock::{doc, temp_storage};
+    use crate::storage::EmbeddingSpec;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn pages_are_capped_at_the_list_maximum() {
+        let (_dir, storage) = temp_storage().await;
+        let documents = (0..LIST_MAX + 5)
+            .map(|i| doc("ns", &format!("{:04}", i), "text", vec![1.0; 2]))
+            .collect();
+        storage
+            .add_to_store(None, documents, &EmbeddingSpec::new("fake", 2))
+            .await
+            .unwrap();
+
+        let options = ListOptions {
+            limit: 1_000_000_000,
+            ..ListOptions::default()
+        };
+        let page = storage.list_documents(None, "ns", &options).await.unwrap();
+        assert_eq!(page.items.len(), LIST_MAX);
+        let options = ListOptions {
+            cursor: page.next_cursor,
+            ..options
+        };
+        let page = storage.list_documents(None, "ns", &options).await.unwrap();
+        assert_eq!(page.items.len(), 5);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let key = SortKey::new(ListSort::Time, Some(42), "doc:1");
+        let cursor = key.encode(ListSort::Time);
+        assert_eq!(SortKey::decode(ListSort::Time, &cursor).unwrap(), key);
+
+        let key = SortKey::new(ListSort::Id, None, "a:b");
+        let cursor = key.encode(ListSort::Id);
+        assert_eq!(SortKey::decode(ListSort::Id, &cursor).unwrap(), key);
+        assert!(SortKey::decode(ListSort::Time, &cursor).is_err());
+    }
+
+    #[test]
+    fn metadata_filter_matches_values_and_arrays() {
+        let metadata = json!({"path": "a.md", "tags": ["x", "y"], "n": 1});
+        let filter = |v: Value| v.as_object().unwrap().clone();
+        assert!(metadata_matches(
+            &metadata,
+            &filter(json!({"path": "a.md"}))
+        ));
+        assert!(metadata_matches(
+            &metadata,
+            &filter(json!({"tags": "y", "n": 1}))
+        ));
+        assert!(!metadata_matches(&metadata, &filter(json!({"tags": "z"}))));
+        assert!(!metadata_matches(&metadata, &filter(json!({"missing": 1}))));
+    }
+
+    #[test]
+    fn document_filter_checks_path_and_tags() {
+        let metadata = json!({"path": "/notes/a.md", "tags": ["todo", "work"]});
+        let filter = DocumentFilter {
+            path: Some("/notes/a.md".to_string()),
+            tags: vec!["todo".to_string()],
+            ..Default::default()
+        };
+        assert!(filter.matches_metadata(&metadata));
+        assert!(!filter.is_unbounded());
+
+        let other_tag = DocumentFilter {
+            tags: vec!["todo".to_string(), "home".to_string()],
+            ..Default::default()
+        };
+        assert!(!other_tag.matches_metadata(&metadata));
+        assert!(DocumentFilter::default().is_unbounded());
+    }
+
+    #[test]
+    fn timestamps_parse_from_dates_and_millis() {
+        assert_eq!(
+            parse_timestamp(&json!(1_700_000_000_000i64)).unwrap(),
+            1_700_000_000_000
+        );
+        assert_eq!(parse_timestamp(&json!("1970-01-02")).unwrap(), 86_400_000);
+        assert_eq!(
+            parse_timestamp(&json!("1970-01-01T00:00:01+00:00")).unwrap(),
+            1_000
+        );
+        assert!(parse_timestamp(&json!("yesterday")).is_err());
+    }
+
+    #[test]
+    fn preview_truncates_on_char_boundary() {
+        let long = "ż".repeat(PREVIEW_CHARS + 5);
+        let (text, truncated) = preview(&long);
+        assert!(truncated);
+        assert_eq!(text.chars().count(), PREVIEW_CHARS + 1);
+        assert_eq!(preview("short"), ("short".to_string(), false));
+    }
+}
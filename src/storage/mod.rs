@@ -1,22 +1,51 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use arrow_array::types::Float32Type;
 use arrow_array::{
-    FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator, StringArray,
+    Array, FixedSizeListArray, Float32Array, Int64Array, RecordBatch, RecordBatchIterator,
+    StringArray,
 };
 use arrow_schema::{ArrowError, DataType, Field, Schema};
-use futures::TryStreamExt;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
 use lancedb::connection::Connection;
-use lancedb::query::{ExecutableQuery, QueryBase};
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::table::{CompactionOptions, NewColumnTransform, OptimizeAction};
 use lancedb::{connect, Table};
 use moka::future::Cache;
 use serde::Serialize;
 use serde_json::{json, Value};
 use sled::Db;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
 use tracing::{debug, info};
 
+mod dedup;
+mod embedding_cache;
+mod export;
+mod kv;
+mod list;
+mod reembed;
+mod registry;
+mod trash;
+
+pub use dedup::{merge_metadata, DedupPolicy, NamespaceSettings};
+use embedding_cache::EmbeddingCacheCounters;
+pub use embedding_cache::{EmbeddingCacheStats, DEFAULT_EMBEDDING_CACHE_MB};
+use export::write_export;
+pub use export::{
+    export_path_in, import_path_in, read_export, ExportFormat, ExportHeader, EXPORT_FORMAT_VERSION,
+};
+pub use kv::{KvEntry, KvPage, KV_LIST_MAX};
+pub use list::{
+    metadata_matches, parse_timestamp, DocumentFilter, DocumentPage, ListOptions, ListSort,
+    ListedDocument, LIST_MAX, PREVIEW_CHARS,
+};
+pub use reembed::RowState;
+pub use registry::{content_hash, DocumentRecord};
+
 #[derive(Debug, Serialize, Clone)]
 pub struct ChromaDocument {
     pub id: String,
@@ -24,14 +53,135 @@ pub struct ChromaDocument {
     pub embedding: Vec<f32>,
     pub metadata: serde_json::Value,
     pub document: String,
+    /// Last write time in Unix milliseconds; `None` lets the store stamp the current time.
+    pub updated_at: Option<i64>,
+}
+
+/// Collection used when neither the config nor the caller picks one.
+pub const DEFAULT_COLLECTION: &str = "mcp_documents";
+
+/// Schema metadata keys recording which model produced a collection's vectors.
+const EMBEDDING_MODEL_KEY: &str = "mcp_memex.embedding_model";
+const EMBEDDING_DIM_KEY: &str = "mcp_memex.embedding_dim";
+
+/// Columns added after the original schema, with the SQL that backfills existing rows.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("updated_at", "CAST(NULL AS BIGINT)"),
+    ("deleted_at", "CAST(NULL AS BIGINT)"),
+    ("content_hash", "encode(sha256(text), 'hex')"),
+];
+
+/// Rows that have not been moved to the trash.
+const LIVE_FILTER: &str = "deleted_at IS NULL";
+
+/// sled key prefix mapping a collection to the Lance table currently backing it. Absent
+/// entries mean the table carries the collection's own name.
+const COLLECTION_TABLE_PREFIX: &str = "collection_table:";
+
+/// sled directory inside the LanceDB directory, so each data directory has its own.
+const SLED_DIR: &str = "_memex.sled";
+
+/// Local parent of the sled stores for remote LanceDB URIs (s3://, gs://, ...), which
+/// sled cannot open.
+const REMOTE_SLED_ROOT: &str = "~/.mcp-servers/mcp_memex/sled";
+
+/// Table versions younger than this survive `optimize` unless configured otherwise.
+pub const DEFAULT_VERSION_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Trashed rows younger than this survive `optimize` and expired-only `trash_empty`.
+pub const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Embedding model id and vector dimension that a collection is bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmbeddingSpec {
+    pub model: String,
+    pub dim: usize,
+}
+
+impl EmbeddingSpec {
+    pub fn new(model: impl Into<String>, dim: usize) -> Self {
+        Self {
+            model: model.into(),
+            dim,
+        }
+    }
 }
 
 pub struct StorageManager {
     cache: Arc<Cache<String, Vec<u8>>>,
     db: Db,
+    /// Source path -> [`DocumentRecord`] of every indexed file.
+    documents: sled::Tree,
+    kv: sled::Tree,
+    namespaces: sled::Tree,
+    /// `{model}\0{text hash}` -> LRU sequence number and vector bytes.
+    embeddings: sled::Tree,
+    /// LRU sequence number -> size and key of an `embeddings` entry, oldest first.
+    embedding_lru: sled::Tree,
+    /// Keys of cached embeddings hit since their LRU positions were last written.
+    embedding_hits: StdMutex<HashSet<String>>,
+    embedding_counters: EmbeddingCacheCounters,
+    embedding_cache_max: u64,
     lance: Connection,
-    table: Arc<Mutex<Option<Table>>>,
+    /// Open Lance tables keyed by collection name (one table per collection).
+    tables: Arc<Mutex<HashMap<String, Table>>>,
+    /// Per-collection gate: every write holds it shared, re-embedding holds it exclusively
+    /// while it catches up and swaps tables.
+    write_locks: StdMutex<HashMap<String, Arc<RwLock<()>>>>,
+    /// Generation tables handed out by [`Self::staging_collection`] and not yet swapped
+    /// in; the only `__g{n}` names calls may address.
+    staging: StdMutex<HashSet<String>>,
     collection_name: String,
+    version_retention: Duration,
+    trash_retention: Duration,
+}
+
+/// Summary of one namespace, as returned by `namespaces_list`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NamespaceStats {
+    pub namespace: String,
+    pub rows: usize,
+    /// Distinct `metadata.path` values; rows without a path count as their own document.
+    pub documents: usize,
+    /// Rows in the trash, not included in `rows`.
+    pub trashed: usize,
+    pub last_updated: Option<String>,
+    pub model: String,
+    pub dim: usize,
+}
+
+/// Outcome of exporting a namespace to a file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportReport {
+    pub collection: String,
+    pub namespace: String,
+    pub path: String,
+    pub format: ExportFormat,
+    pub model: String,
+    pub dim: usize,
+    pub rows: usize,
+}
+
+/// One entry of a collection's version history.
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub version: u64,
+    pub timestamp: String,
+    pub rows: usize,
+}
+
+/// Outcome of compacting and pruning one collection.
+#[derive(Debug, Clone, Serialize)]
+pub struct OptimizeReport {
+    pub collection: String,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub versions_before: usize,
+    pub versions_after: usize,
+    pub fragments_removed: usize,
+    pub fragments_added: usize,
+    pub old_versions_removed: u64,
+    pub trash_purged: usize,
 }
 
 type BatchIter =
@@ -44,12 +194,9 @@ impl StorageManager {
         let cache = Cache::builder()
             .max_capacity(cache_bytes as u64)
             .time_to_live(Duration::from_secs(3600))
+            .support_invalidation_closures()
             .build();
 
-        // Persistent K/V for auxiliary state
-        let sled_path = shellexpand::tilde("~/.mcp-servers/sled").to_string();
-        let db = sled::open(sled_path)?;
-
         // Embedded LanceDB path (expand ~, allow override via env)
         let lance_env = std::env::var("LANCEDB_PATH").unwrap_or_else(|_| db_path.to_string());
         let lance_path = if lance_env.trim().is_empty() {
@@ -60,41 +207,414 @@ impl StorageManager {
 
         let lance = connect(&lance_path).execute().await?;
 
+        // Persistent K/V for auxiliary state, private to this data directory
+        let sled_path = sled_path_for(&lance_path);
+        let db = sled::open(&sled_path)
+            .with_context(|| format!("Cannot open sled store at {}", sled_path.display()))?;
+        let documents = db.open_tree("documents")?;
+        let kv = db.open_tree("kv")?;
+        let namespaces = db.open_tree("namespaces")?;
+        let embeddings = db.open_tree("embeddings")?;
+        let embedding_lru = db.open_tree("embedding_lru")?;
+        let embedding_counters = EmbeddingCacheCounters::load(&embedding_lru)?;
+
         Ok(Self {
             cache: Arc::new(cache),
             db,
+            documents,
+            kv,
+            namespaces,
+            embeddings,
+            embedding_lru,
+            embedding_hits: StdMutex::new(HashSet::new()),
+            embedding_counters,
+            embedding_cache_max: (DEFAULT_EMBEDDING_CACHE_MB * 1024 * 1024) as u64,
             lance,
-            table: Arc::new(Mutex::new(None)),
-            collection_name: "mcp_documents".to_string(),
+            tables: Arc::new(Mutex::new(HashMap::new())),
+            write_locks: StdMutex::new(HashMap::new()),
+            staging: StdMutex::new(HashSet::new()),
+            collection_name: DEFAULT_COLLECTION.to_string(),
+            version_retention: DEFAULT_VERSION_RETENTION,
+            trash_retention: DEFAULT_TRASH_RETENTION,
         })
     }
 
+    /// How long deleted rows stay restorable before `optimize` removes them.
+    pub fn with_trash_retention(mut self, retention: Duration) -> Self {
+        self.trash_retention = retention;
+        self
+    }
+
+    /// Size cap of the persistent embedding cache; 0 disables it.
+    pub fn with_embedding_cache_limit(mut self, max_bytes: u64) -> Self {
+        self.embedding_cache_max = max_bytes;
+        self
+    }
+
+    /// How long old table versions are kept when `optimize` prunes history.
+    pub fn with_version_retention(mut self, retention: Duration) -> Self {
+        self.version_retention = retention;
+        self
+    }
+
+    /// Override the collection used when callers do not name one.
+    pub fn with_default_collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection_name = collection.into();
+        self
+    }
+
     pub async fn ensure_collection(&self) -> Result<()> {
         // Attempt to open; if missing, create empty table lazily on first add
-        let mut guard = self.table.lock().await;
-        if guard.is_some() {
-            return Ok(());
+        let collection = self.resolve_collection(None)?;
+        match self.open_table(collection).await? {
+            Some(_) => info!("Found existing Lance table '{}'", collection),
+            None => info!(
+                "Lance table '{}' will be created on first insert",
+                collection
+            ),
         }
-        match self
+        Ok(())
+    }
+
+    /// Names of all collections present in the store. Generation tables written by
+    /// re-embedding are reported under the collection they back.
+    pub async fn list_collections(&self) -> Result<Vec<String>> {
+        let mut names = self
             .lance
-            .open_table(self.collection_name.as_str())
+            .table_names()
             .execute()
-            .await
+            .await?
+            .into_iter()
+            .filter(|name| generation_of(name).is_none())
+            .collect::<Vec<_>>();
+        for entry in self.db.scan_prefix(COLLECTION_TABLE_PREFIX) {
+            let (key, _) = entry?;
+            let key = String::from_utf8(key.to_vec())?;
+            names.push(key[COLLECTION_TABLE_PREFIX.len()..].to_string());
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Collection name a call resolves to once the configured default is applied.
+    pub fn collection_or_default(&self, collection: Option<&str>) -> Result<String> {
+        Ok(self.resolve_collection(collection)?.to_string())
+    }
+
+    /// Name of the collection a re-embed of `collection` writes into before it is swapped
+    /// in. The name only depends on the current generation, so interrupted runs resume.
+    pub async fn staging_collection(&self, collection: Option<&str>) -> Result<String> {
+        let collection = self.resolve_collection(collection)?;
+        let current = self.table_name(collection).await?;
+        let generation = generation_of(&current).map(|(_, g)| g).unwrap_or(0);
+        let staging = format!("{}__g{}", collection, generation + 1);
+        self.staging
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(staging.clone());
+        Ok(staging)
+    }
+
+    /// Point `collection` at the table built under `staging` and drop the table it
+    /// replaces. The switch is a single sled write, so readers see either table whole.
+    pub async fn swap_collection(&self, collection: Option<&str>, staging: &str) -> Result<()> {
+        let collection = self.resolve_collection(collection)?;
+        let staging = self.resolve_collection(Some(staging))?;
+        let old = self.table_name(collection).await?;
+        let staging_table = self
+            .open_table(staging)
+            .await?
+            .ok_or_else(|| anyhow!("Staging collection '{}' does not exist", staging))?;
+
+        self.set(
+            &format!("{}{}", COLLECTION_TABLE_PREFIX, collection),
+            staging.as_bytes().to_vec(),
+        )
+        .await?;
         {
-            Ok(table) => {
-                *guard = Some(table);
-                info!("Found existing Lance table '{}'", self.collection_name);
-            }
-            Err(_) => {
-                info!(
-                    "Lance table '{}' will be created on first insert",
-                    self.collection_name
-                );
+            let mut guard = self.tables.lock().await;
+            guard.remove(staging);
+            guard.insert(collection.to_string(), staging_table);
+        }
+        self.staging
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(staging);
+
+        if old != staging {
+            match self.lance.drop_table(&old, &[]).await {
+                Ok(()) | Err(lancedb::Error::TableNotFound { .. }) => {}
+                Err(e) => return Err(e.into()),
             }
         }
+        info!(
+            "Collection '{}' now served by table '{}' (replaced '{}')",
+            collection, staging, old
+        );
         Ok(())
     }
 
+    /// Remove trash past its grace period, compact small fragments and prune versions
+    /// older than `retention` (defaults to the configured retention window). Returns
+    /// `None` if the collection does not exist.
+    pub async fn optimize_collection(
+        &self,
+        collection: Option<&str>,
+        retention: Option<Duration>,
+    ) -> Result<Option<OptimizeReport>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(None);
+        };
+        let retention = retention.unwrap_or(self.version_retention);
+        let table_dir = table_path(&table);
+        let bytes_before = dir_size(table_dir.clone()).await?;
+        let versions_before = table.list_versions().await?.len();
+
+        let trash_purged = self.empty_trash(Some(collection), None, true).await?;
+        let compacted = table
+            .optimize(OptimizeAction::Compact {
+                options: CompactionOptions::default(),
+                remap_options: None,
+            })
+            .await?;
+        // This process is the only writer, so unverified files past the window are
+        // leftovers rather than in-flight transactions and can go too.
+        let pruned = table
+            .optimize(OptimizeAction::Prune {
+                older_than: Some(lancedb::table::Duration::from_std(retention)?),
+                delete_unverified: Some(true),
+                error_if_tagged_old_versions: Some(false),
+            })
+            .await?;
+
+        let compaction = compacted.compaction.unwrap_or_default();
+        let report = OptimizeReport {
+            collection: collection.to_string(),
+            bytes_before,
+            bytes_after: dir_size(table_dir).await?,
+            versions_before,
+            versions_after: table.list_versions().await?.len(),
+            fragments_removed: compaction.fragments_removed,
+            fragments_added: compaction.fragments_added,
+            old_versions_removed: pruned.prune.map(|p| p.old_versions).unwrap_or_default(),
+            trash_purged,
+        };
+        info!(
+            "Optimized '{}': {} -> {} bytes, {} -> {} versions",
+            collection,
+            report.bytes_before,
+            report.bytes_after,
+            report.versions_before,
+            report.versions_after
+        );
+        Ok(Some(report))
+    }
+
+    /// Table versions of a collection, newest first, with the live row count (of one
+    /// namespace, if given) at each version.
+    pub async fn list_versions(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<VersionInfo>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(vec![]);
+        };
+        let mut versions = table.list_versions().await?;
+        versions.sort_by_key(|v| std::cmp::Reverse(v.version));
+        versions.truncate(limit);
+
+        let snapshot = self
+            .lance
+            .open_table(self.table_name(collection).await?)
+            .execute()
+            .await?;
+        let mut out = Vec::with_capacity(versions.len());
+        for version in versions {
+            snapshot.checkout(version.version).await?;
+            // Older versions may predate the trash column.
+            let filter = live_filter(&snapshot, namespace.map(|ns| self.namespace_filter(ns)));
+            out.push(VersionInfo {
+                version: version.version,
+                timestamp: version.timestamp.to_rfc3339(),
+                rows: snapshot.count_rows(filter.await?).await?,
+            });
+        }
+        Ok(out)
+    }
+
+    /// Replace the current rows of a namespace with the rows it had at `version`. Rows
+    /// written since that are not part of it move to the trash, so the restore itself
+    /// can be undone. Other namespaces are untouched. Returns the number of rows restored.
+    pub async fn restore_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        version: u64,
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let table = self
+            .open_table(collection)
+            .await?
+            .ok_or_else(|| anyhow!("Collection '{}' does not exist", collection))?;
+        let snapshot = self
+            .table_at(collection, Some(version))
+            .await?
+            .ok_or_else(|| anyhow!("Collection '{}' does not exist", collection))?;
+
+        // Rows trashed at `version` stay out; the current trash is left alone.
+        let snapshot_filter = live_filter(&snapshot, Some(self.namespace_filter(namespace)))
+            .await?
+            .unwrap_or_default();
+        let mut stream = snapshot
+            .query()
+            .only_if(snapshot_filter.as_str())
+            .execute()
+            .await?;
+        let mut documents = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            documents.append(&mut batch_to_docs(&batch)?);
+        }
+
+        let restored = documents
+            .iter()
+            .map(|d| (d.namespace.clone(), d.id.clone()))
+            .collect::<HashSet<_>>();
+        let newer = self
+            .row_states(Some(collection), Some(namespace))
+            .await?
+            .into_iter()
+            .filter(|(key, state)| state.deleted_at.is_none() && !restored.contains(key))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        if !newer.is_empty() {
+            trash_matching(&table, &self.keys_filter(&newer)).await?;
+        }
+        if !documents.is_empty() {
+            let (_, dim) = stored_embedding(&table).await?;
+            let batch = self.docs_to_batch(&documents, dim)?;
+            let mut merge = table.merge_insert(&["namespace", "id"]);
+            merge
+                .when_matched_update_all(None)
+                .when_not_matched_insert_all();
+            merge.execute(Box::new(batch)).await?;
+        }
+        info!(
+            "Restored namespace '{}' in '{}' to version {} ({} rows, {} newer rows trashed)",
+            namespace,
+            collection,
+            version,
+            documents.len(),
+            newer.len()
+        );
+        Ok(documents.len())
+    }
+
+    /// Optimize every collection in the store.
+    pub async fn optimize_all(&self, retention: Option<Duration>) -> Result<Vec<OptimizeReport>> {
+        let mut reports = Vec::new();
+        for collection in self.list_collections().await? {
+            if let Some(report) = self
+                .optimize_collection(Some(&collection), retention)
+                .await?
+            {
+                reports.push(report);
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Stream every document of a collection (optionally one namespace) batch by batch.
+    pub async fn stream_documents(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+    ) -> Result<BoxStream<'static, Result<Vec<ChromaDocument>>>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(futures::stream::empty().boxed());
+        };
+        let filter = self.live_namespace_filter(namespace);
+        let stream = table.query().only_if(filter).execute().await?;
+        Ok(stream.map(|batch| batch_to_docs(&batch?)).boxed())
+    }
+
+    /// Row count, source documents and last write per namespace, read from the scalar
+    /// columns only.
+    pub async fn namespace_stats(&self, collection: Option<&str>) -> Result<Vec<NamespaceStats>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(Vec::new());
+        };
+        let (model, dim) = stored_embedding(&table).await?;
+        let model = model.unwrap_or_else(|| "unknown".to_string());
+
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec![
+                "namespace".to_string(),
+                "id".to_string(),
+                "metadata".to_string(),
+                "updated_at".to_string(),
+                "deleted_at".to_string(),
+            ]))
+            .execute()
+            .await?;
+        // namespace -> (rows, source documents, newest updated_at, trashed rows)
+        let mut acc: HashMap<String, (usize, HashSet<String>, Option<i64>, usize)> = HashMap::new();
+        while let Some(batch) = stream.try_next().await? {
+            let ns_col = string_column(&batch, "namespace")?;
+            let id_col = string_column(&batch, "id")?;
+            let metadata_col = string_column(&batch, "metadata")?;
+            let updated_col = batch
+                .column_by_name("updated_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            let deleted_col = batch
+                .column_by_name("deleted_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            for i in 0..batch.num_rows() {
+                let entry = acc.entry(ns_col.value(i).to_string()).or_default();
+                if deleted_col.is_some_and(|c| c.is_valid(i)) {
+                    entry.3 += 1;
+                    continue;
+                }
+                entry.0 += 1;
+                let metadata: Value =
+                    serde_json::from_str(metadata_col.value(i)).unwrap_or_default();
+                let source = match metadata.get("path").and_then(Value::as_str) {
+                    Some(path) => format!("path:{}", path),
+                    None => format!("id:{}", id_col.value(i)),
+                };
+                entry.1.insert(source);
+                if let Some(ts) = updated_col.filter(|c| c.is_valid(i)).map(|c| c.value(i)) {
+                    entry.2 = entry.2.max(Some(ts));
+                }
+            }
+        }
+
+        let mut stats = acc
+            .into_iter()
+            .map(
+                |(namespace, (rows, sources, updated, trashed))| NamespaceStats {
+                    namespace,
+                    rows,
+                    documents: sources.len(),
+                    trashed,
+                    last_updated: updated.and_then(format_millis),
+                    model: model.clone(),
+                    dim,
+                },
+            )
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        Ok(stats)
+    }
+
     pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
         if let Some(value) = self.cache.get(key).await {
             return Ok(Some(value));
@@ -114,63 +634,179 @@ impl StorageManager {
         Ok(())
     }
 
-    pub async fn add_to_store(&self, documents: Vec<ChromaDocument>) -> Result<()> {
+    /// Embedding model and dimension recorded for a collection, if it exists. Tables
+    /// created before the model was recorded report `"unknown"` as the model.
+    pub async fn collection_embedding(
+        &self,
+        collection: Option<&str>,
+    ) -> Result<Option<EmbeddingSpec>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(None);
+        };
+        let (model, dim) = stored_embedding(&table).await?;
+        Ok(Some(EmbeddingSpec::new(
+            model.unwrap_or_else(|| "unknown".to_string()),
+            dim,
+        )))
+    }
+
+    pub async fn add_to_store(
+        &self,
+        collection: Option<&str>,
+        documents: Vec<ChromaDocument>,
+        spec: &EmbeddingSpec,
+    ) -> Result<()> {
         if documents.is_empty() {
             return Ok(());
         }
-        let dim = documents
-            .first()
-            .ok_or_else(|| anyhow!("No documents to add"))?
-            .embedding
-            .len();
-        if dim == 0 {
+        if spec.dim == 0 {
             return Err(anyhow!("Embedding dimension is zero"));
         }
 
-        let table = self.ensure_table(dim).await?;
-        let batch = self.docs_to_batch(&documents, dim)?;
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let table = self.ensure_table(collection, spec).await?;
+        let batch = self.docs_to_batch(&documents, spec.dim)?;
         table.add(batch).execute().await?;
         debug!("Inserted {} documents into Lance", documents.len());
         Ok(())
     }
 
+    /// Insert documents, replacing rows that share a `(namespace, id)` key.
+    pub async fn upsert_to_store(
+        &self,
+        collection: Option<&str>,
+        documents: Vec<ChromaDocument>,
+        spec: &EmbeddingSpec,
+    ) -> Result<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        if spec.dim == 0 {
+            return Err(anyhow!("Embedding dimension is zero"));
+        }
+
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let table = self.ensure_table(collection, spec).await?;
+        let batch = self.docs_to_batch(&documents, spec.dim)?;
+        let mut merge = table.merge_insert(&["namespace", "id"]);
+        merge
+            .when_matched_update_all(None)
+            .when_not_matched_insert_all();
+        merge.execute(Box::new(batch)).await?;
+        debug!("Upserted {} documents into Lance", documents.len());
+        Ok(())
+    }
+
+    /// Write every row of a namespace (vectors included) to `path`.
+    pub async fn export_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        path: &Path,
+        format: ExportFormat,
+    ) -> Result<ExportReport> {
+        let collection = self.resolve_collection(collection)?;
+        let spec = self
+            .collection_embedding(Some(collection))
+            .await?
+            .ok_or_else(|| anyhow!("Collection '{}' does not exist", collection))?;
+        let header = ExportHeader {
+            format: EXPORT_FORMAT_VERSION.to_string(),
+            collection: collection.to_string(),
+            namespace: namespace.to_string(),
+            model: spec.model.clone(),
+            dim: spec.dim,
+            exported_at: now_millis(),
+        };
+
+        let stream = self
+            .stream_documents(Some(collection), Some(namespace))
+            .await?;
+        let rows = write_export(path, format, &header, stream).await?;
+        info!(
+            "Exported {} rows of namespace '{}' in '{}' to {}",
+            rows,
+            namespace,
+            collection,
+            path.display()
+        );
+        Ok(ExportReport {
+            collection: collection.to_string(),
+            namespace: namespace.to_string(),
+            path: path.display().to_string(),
+            format,
+            model: spec.model,
+            dim: spec.dim,
+            rows,
+        })
+    }
+
     pub async fn search_store(
         &self,
+        collection: Option<&str>,
         namespace: Option<&str>,
         embedding: Vec<f32>,
         k: usize,
+        spec: &EmbeddingSpec,
+        version: Option<u64>,
     ) -> Result<Vec<ChromaDocument>> {
         if embedding.is_empty() {
             return Ok(vec![]);
         }
-        let dim = embedding.len();
-        let table = self.ensure_table(dim).await?;
+        if embedding.len() != spec.dim {
+            return Err(anyhow!(
+                "Query embedding has {} dimensions but model '{}' declares {}",
+                embedding.len(),
+                spec.model,
+                spec.dim
+            ));
+        }
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(vec![]);
+        };
+        self.check_embedding(collection, &table, spec).await?;
+        let Some(table) = self.table_at(collection, version).await? else {
+            return Ok(vec![]);
+        };
 
         let mut query = table.query();
-        if let Some(ns) = namespace {
-            query = query.only_if(self.namespace_filter(ns).as_str());
+        if let Some(filter) =
+            live_filter(&table, namespace.map(|ns| self.namespace_filter(ns))).await?
+        {
+            query = query.only_if(filter);
         }
         let mut stream = query.nearest_to(embedding)?.limit(k).execute().await?;
 
         let mut results = Vec::new();
         while let Some(batch) = stream.try_next().await? {
-            let mut docs = self.batch_to_docs(&batch)?;
+            let mut docs = batch_to_docs(&batch)?;
             results.append(&mut docs);
         }
         debug!("Lance returned {} results", results.len());
         Ok(results)
     }
 
-    pub async fn get_document(&self, namespace: &str, id: &str) -> Result<Option<ChromaDocument>> {
-        let table = match self.ensure_table(0).await {
-            Ok(t) => t,
-            Err(_) => return Ok(None),
+    pub async fn get_document(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        id: &str,
+        version: Option<u64>,
+    ) -> Result<Option<ChromaDocument>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.table_at(collection, version).await? else {
+            return Ok(None);
         };
         let filter = format!(
             "{} AND {}",
             self.namespace_filter(namespace),
             self.id_filter(id)
         );
+        let filter = live_filter(&table, Some(filter)).await?.unwrap_or_default();
         let mut stream = table
             .query()
             .only_if(filter.as_str())
@@ -178,7 +814,7 @@ impl StorageManager {
             .execute()
             .await?;
         if let Some(batch) = stream.try_next().await? {
-            let mut docs = self.batch_to_docs(&batch)?;
+            let mut docs = batch_to_docs(&batch)?;
             if let Some(doc) = docs.pop() {
                 return Ok(Some(doc));
             }
@@ -186,201 +822,958 @@ impl StorageManager {
         Ok(None)
     }
 
-    pub async fn delete_document(&self, namespace: &str, id: &str) -> Result<usize> {
-        let table = match self.ensure_table(0).await {
-            Ok(t) => t,
-            Err(_) => return Ok(0),
+    pub async fn delete_document(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        id: &str,
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(0);
         };
         let predicate = format!(
             "{} AND {}",
             self.namespace_filter(namespace),
             self.id_filter(id)
         );
-        let deleted = table.delete(predicate.as_str()).await?;
-        Ok(deleted.version as usize)
+        trash_matching(&table, &predicate).await
+    }
+
+    /// Which of the `(namespace, id)` keys are stored (and not trashed) in a collection.
+    pub async fn existing_keys(
+        &self,
+        collection: Option<&str>,
+        keys: &[(String, String)],
+    ) -> Result<HashSet<(String, String)>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(HashSet::new());
+        };
+        if keys.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let filter = format!("({}) AND {}", self.keys_filter(keys), LIVE_FILTER);
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec![
+                "namespace".to_string(),
+                "id".to_string(),
+            ]))
+            .only_if(filter.as_str())
+            .execute()
+            .await?;
+        let mut found = HashSet::new();
+        while let Some(batch) = stream.try_next().await? {
+            let ns_col = string_column(&batch, "namespace")?;
+            let id_col = string_column(&batch, "id")?;
+            for i in 0..batch.num_rows() {
+                found.insert((ns_col.value(i).to_string(), id_col.value(i).to_string()));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Move every listed `(namespace, id)` key to the trash in one commit and return the
+    /// keys that existed.
+    pub async fn delete_keys(
+        &self,
+        collection: Option<&str>,
+        keys: &[(String, String)],
+    ) -> Result<HashSet<(String, String)>> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let found = self.existing_keys(Some(collection), keys).await?;
+        if found.is_empty() {
+            return Ok(found);
+        }
+        if let Some(table) = self.open_table(collection).await? {
+            trash_matching(&table, &self.keys_filter(keys)).await?;
+        }
+        Ok(found)
     }
 
-    pub async fn purge_namespace(&self, namespace: &str) -> Result<usize> {
-        let table = match self.ensure_table(0).await {
-            Ok(t) => t,
-            Err(_) => return Ok(0),
+    pub async fn purge_namespace(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(0);
         };
         let predicate = self.namespace_filter(namespace);
-        let deleted = table.delete(predicate.as_str()).await?;
-        Ok(deleted.version as usize)
+        trash_matching(&table, &predicate).await
     }
 
     pub fn get_collection_name(&self) -> &str {
         &self.collection_name
     }
 
-    async fn ensure_table(&self, dim: usize) -> Result<Table> {
-        let mut guard = self.table.lock().await;
-        if let Some(table) = guard.as_ref() {
-            return Ok(table.clone());
+    /// Map an optional per-call collection onto a validated table name.
+    fn resolve_collection<'a>(&'a self, collection: Option<&'a str>) -> Result<&'a str> {
+        let name = collection
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .unwrap_or(self.collection_name.as_str());
+        let staging = self.staging.lock().unwrap_or_else(|e| e.into_inner());
+        if staging.contains(name) {
+            check_table_name(name)?;
+        } else {
+            validate_collection_name(name)?;
+        }
+        Ok(name)
+    }
+
+    /// Block writes to a collection until the guard is dropped. Re-embedding holds it
+    /// while it copies the last changes and swaps tables, so nothing written in between
+    /// is lost; it must not write to the collection itself meanwhile.
+    pub async fn pause_writes(
+        &self,
+        collection: Option<&str>,
+    ) -> Result<OwnedRwLockWriteGuard<()>> {
+        let collection = self.resolve_collection(collection)?;
+        Ok(self.write_lock(collection).write_owned().await)
+    }
+
+    /// Shared hold on a collection's write gate, taken before a write opens the table.
+    async fn writing(&self, collection: &str) -> OwnedRwLockReadGuard<()> {
+        self.write_lock(collection).read_owned().await
+    }
+
+    fn write_lock(&self, collection: &str) -> Arc<RwLock<()>> {
+        let mut locks = self.write_locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.entry(collection.to_string()).or_default().clone()
+    }
+
+    /// Lance table currently backing a collection.
+    async fn table_name(&self, collection: &str) -> Result<String> {
+        let key = format!("{}{}", COLLECTION_TABLE_PREFIX, collection);
+        match self.get(&key).await? {
+            Some(raw) => Ok(String::from_utf8(raw)?),
+            None => Ok(collection.to_string()),
         }
+    }
 
-        let maybe_table = self
+    /// The live table, or a read-only snapshot of it checked out at `version`.
+    async fn table_at(&self, collection: &str, version: Option<u64>) -> Result<Option<Table>> {
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(None);
+        };
+        let Some(version) = version else {
+            return Ok(Some(table));
+        };
+        // Checkout detaches the handle it is called on, so use a private one.
+        let snapshot = self
             .lance
-            .open_table(self.collection_name.as_str())
+            .open_table(self.table_name(collection).await?)
             .execute()
-            .await;
+            .await?;
+        snapshot.checkout(version).await?;
+        Ok(Some(snapshot))
+    }
 
-        let table = if let Ok(tbl) = maybe_table {
-            tbl
-        } else {
-            if dim == 0 {
-                return Err(anyhow!(
-                    "Vector table '{}' not found and dimension is unknown",
-                    self.collection_name
-                ));
+    /// Open an existing collection table, caching the handle. Returns `None` when the
+    /// table has not been created yet.
+    async fn open_table(&self, collection: &str) -> Result<Option<Table>> {
+        let mut guard = self.tables.lock().await;
+        if let Some(table) = guard.get(collection) {
+            return Ok(Some(table.clone()));
+        }
+        let name = self.table_name(collection).await?;
+        match self.lance.open_table(name).execute().await {
+            Ok(table) => {
+                upgrade_schema(collection, &table).await?;
+                guard.insert(collection.to_string(), table.clone());
+                Ok(Some(table))
             }
-            info!(
-                "Creating Lance table '{}' with vector dimension {}",
-                self.collection_name, dim
-            );
-            let schema = Arc::new(Schema::new(vec![
-                Field::new("id", DataType::Utf8, false),
-                Field::new("namespace", DataType::Utf8, false),
-                Field::new(
-                    "vector",
-                    DataType::FixedSizeList(
-                        Arc::new(Field::new("item", DataType::Float32, true)),
-                        dim as i32,
-                    ),
-                    false,
-                ),
-                Field::new("text", DataType::Utf8, true),
-                Field::new("metadata", DataType::Utf8, true),
-            ]));
-            self.lance
-                .create_empty_table(self.collection_name.as_str(), schema)
-                .execute()
-                .await?
-        };
+            Err(lancedb::Error::TableNotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn ensure_table(&self, collection: &str, spec: &EmbeddingSpec) -> Result<Table> {
+        if let Some(table) = self.open_table(collection).await? {
+            self.check_embedding(collection, &table, spec).await?;
+            return Ok(table);
+        }
 
-        *guard = Some(table.clone());
+        let mut guard = self.tables.lock().await;
+        if let Some(table) = guard.get(collection) {
+            return Ok(table.clone());
+        }
+        info!(
+            "Creating Lance table '{}' for model '{}' with vector dimension {}",
+            collection, spec.model, spec.dim
+        );
+        let name = self.table_name(collection).await?;
+        let table = self
+            .lance
+            .create_empty_table(name, table_schema(spec))
+            .execute()
+            .await?;
+
+        guard.insert(collection.to_string(), table.clone());
         Ok(table)
     }
 
+    /// Refuse to mix vectors from different models or dimensions in one table. Tables
+    /// that predate recorded metadata adopt the active model when dimensions agree.
+    async fn check_embedding(
+        &self,
+        collection: &str,
+        table: &Table,
+        spec: &EmbeddingSpec,
+    ) -> Result<()> {
+        let (model, dim) = stored_embedding(table).await?;
+        if dim != spec.dim {
+            return Err(anyhow!(
+                "Collection '{}' stores {}-dim vectors{} but the active embedder '{}' produces {}-dim vectors; \
+                 use the original embedding provider or re-embed the collection",
+                collection,
+                dim,
+                model
+                    .as_deref()
+                    .map(|m| format!(" from '{}'", m))
+                    .unwrap_or_default(),
+                spec.model,
+                spec.dim
+            ));
+        }
+        match model {
+            Some(model) if model != spec.model => Err(anyhow!(
+                "Collection '{}' was embedded with '{}' but the active embedder is '{}'; \
+                 use the original embedding provider or re-embed the collection",
+                collection,
+                model,
+                spec.model
+            )),
+            Some(_) => Ok(()),
+            None => {
+                let native = table
+                    .as_native()
+                    .ok_or_else(|| anyhow!("Collection '{}' is not a local table", collection))?;
+                native
+                    .replace_schema_metadata(embedding_metadata(spec))
+                    .await?;
+                info!(
+                    "Recorded embedding model '{}' ({} dims) for existing collection '{}'",
+                    spec.model, spec.dim, collection
+                );
+                Ok(())
+            }
+        }
+    }
+
     fn docs_to_batch(&self, documents: &[ChromaDocument], dim: usize) -> Result<BatchIter> {
-        let ids = documents.iter().map(|d| d.id.as_str()).collect::<Vec<_>>();
-        let namespaces = documents
-            .iter()
-            .map(|d| d.namespace.as_str())
-            .collect::<Vec<_>>();
-        let texts = documents
-            .iter()
-            .map(|d| d.document.as_str())
-            .collect::<Vec<_>>();
-        let metadata_strings = documents
-            .iter()
-            .map(|d| serde_json::to_string(&d.metadata).unwrap_or_else(|_| "{}".to_string()))
+        let batch = docs_to_record_batch(documents, dim, Some(now_millis()))?;
+        let schema = batch.schema();
+        Ok(RecordBatchIterator::new(
+            vec![Ok(batch)].into_iter(),
+            schema,
+        ))
+    }
+
+    fn namespace_filter(&self, namespace: &str) -> String {
+        format!("namespace = '{}'", namespace.replace('\'', "''"))
+    }
+
+    fn id_filter(&self, id: &str) -> String {
+        format!("id = '{}'", id.replace('\'', "''"))
+    }
+
+    /// Live rows, optionally limited to one namespace.
+    fn live_namespace_filter(&self, namespace: Option<&str>) -> String {
+        match namespace {
+            Some(ns) => format!("{} AND {}", self.namespace_filter(ns), LIVE_FILTER),
+            None => LIVE_FILTER.to_string(),
+        }
+    }
+
+    /// Filter matching any of the keys, grouped by namespace.
+    fn keys_filter(&self, keys: &[(String, String)]) -> String {
+        let mut by_namespace: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (namespace, id) in keys {
+            by_namespace.entry(namespace).or_default().push(id);
+        }
+        by_namespace
+            .into_iter()
+            .map(|(namespace, ids)| {
+                format!(
+                    "({} AND {})",
+                    self.namespace_filter(namespace),
+                    self.id_list_filter(ids.into_iter())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    }
+
+    fn id_list_filter<'a>(&self, ids: impl Iterator<Item = &'a str>) -> String {
+        let quoted = ids
+            .map(|id| format!("'{}'", id.replace('\'', "''")))
             .collect::<Vec<_>>();
+        format!("id IN ({})", quoted.join(", "))
+    }
+}
 
-        let vectors = documents.iter().map(|d| {
-            if d.embedding.len() != dim {
-                None
-            } else {
-                Some(d.embedding.iter().map(|v| Some(*v)).collect::<Vec<_>>())
-            }
+/// Build a record batch in the table layout. Rows without `updated_at` get `stamp`.
+fn docs_to_record_batch(
+    documents: &[ChromaDocument],
+    dim: usize,
+    stamp: Option<i64>,
+) -> Result<RecordBatch> {
+    if let Some(doc) = documents.iter().find(|d| d.embedding.len() != dim) {
+        return Err(anyhow!(
+            "Document '{}' has a {}-dim embedding, expected {}",
+            doc.id,
+            doc.embedding.len(),
+            dim
+        ));
+    }
+
+    let ids = documents.iter().map(|d| d.id.as_str()).collect::<Vec<_>>();
+    let namespaces = documents
+        .iter()
+        .map(|d| d.namespace.as_str())
+        .collect::<Vec<_>>();
+    let texts = documents
+        .iter()
+        .map(|d| d.document.as_str())
+        .collect::<Vec<_>>();
+    let metadata_strings = documents
+        .iter()
+        .map(|d| serde_json::to_string(&d.metadata).unwrap_or_else(|_| "{}".to_string()))
+        .collect::<Vec<_>>();
+
+    let vectors = documents
+        .iter()
+        .map(|d| Some(d.embedding.iter().map(|v| Some(*v)).collect::<Vec<_>>()));
+    let updated_at = documents
+        .iter()
+        .map(|d| d.updated_at.or(stamp))
+        .collect::<Vec<_>>();
+    let hashes = documents
+        .iter()
+        .map(|d| content_hash(d.document.as_bytes()))
+        .collect::<Vec<_>>();
+
+    let schema = table_schema_for_dim(dim);
+
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(ids)),
+            Arc::new(StringArray::from(namespaces)),
+            Arc::new(
+                FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(vectors, dim as i32),
+            ),
+            Arc::new(StringArray::from(texts)),
+            Arc::new(StringArray::from(metadata_strings)),
+            Arc::new(Int64Array::from(updated_at)),
+            Arc::new(Int64Array::from(vec![None::<i64>; documents.len()])),
+            Arc::new(StringArray::from(hashes)),
+        ],
+    )?;
+    Ok(batch)
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+        .ok_or_else(|| anyhow!("Missing {} column", name))
+}
+
+fn batch_to_docs(batch: &RecordBatch) -> Result<Vec<ChromaDocument>> {
+    let id_col = string_column(batch, "id")?;
+    let ns_col = string_column(batch, "namespace")?;
+    let text_col = string_column(batch, "text")?;
+    let metadata_col = string_column(batch, "metadata")?;
+    // Absent in snapshots taken before the column was added.
+    let updated_col = batch
+        .column_by_name("updated_at")
+        .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+    let vector_col = batch
+        .column_by_name("vector")
+        .and_then(|c| c.as_any().downcast_ref::<FixedSizeListArray>())
+        .ok_or_else(|| anyhow!("Missing vector column"))?;
+
+    let dim = vector_col.value_length() as usize;
+    let values = vector_col
+        .values()
+        .as_any()
+        .downcast_ref::<Float32Array>()
+        .ok_or_else(|| anyhow!("Vector inner type mismatch"))?;
+
+    let mut docs = Vec::new();
+    for i in 0..batch.num_rows() {
+        let id = id_col.value(i).to_string();
+        let text = text_col.value(i).to_string();
+        let namespace = ns_col.value(i).to_string();
+        let meta_str = metadata_col.value(i);
+        let metadata: Value = serde_json::from_str(meta_str).unwrap_or_else(|_| json!({}));
+
+        let offset = i * dim;
+        let mut emb = Vec::with_capacity(dim);
+        for j in 0..dim {
+            emb.push(values.value(offset + j));
+        }
+
+        let updated_at = updated_col.filter(|c| c.is_valid(i)).map(|c| c.value(i));
+
+        docs.push(ChromaDocument {
+            id,
+            namespace,
+            embedding: emb,
+            metadata,
+            document: text,
+            updated_at,
         });
+    }
+    Ok(docs)
+}
+
+/// Delete rows matching `predicate` and return how many there were.
+async fn delete_counted(table: &Table, predicate: &str) -> Result<usize> {
+    let rows = table.count_rows(Some(predicate.to_string())).await?;
+    if rows > 0 {
+        table.delete(predicate).await?;
+    }
+    Ok(rows)
+}
+
+/// Stamp `deleted_at` on live rows matching `predicate` and return how many there were.
+async fn trash_matching(table: &Table, predicate: &str) -> Result<usize> {
+    let updated = table
+        .update()
+        .only_if(format!("({}) AND {}", predicate, LIVE_FILTER))
+        .column("deleted_at", now_millis().to_string())
+        .execute()
+        .await?;
+    Ok(updated.rows_updated as usize)
+}
+
+/// Restrict `predicate` to live rows when the table (or snapshot) has a trash column.
+async fn live_filter(table: &Table, predicate: Option<String>) -> Result<Option<String>> {
+    let has_trash = table.schema().await?.field_with_name("deleted_at").is_ok();
+    Ok(match (predicate, has_trash) {
+        (Some(p), true) => Some(format!("{} AND {}", p, LIVE_FILTER)),
+        (None, true) => Some(LIVE_FILTER.to_string()),
+        (p, false) => p,
+    })
+}
+
+/// Where the sled store for a LanceDB path lives: inside the directory when it is
+/// local, otherwise in a local directory named after the URI.
+fn sled_path_for(lance_path: &str) -> PathBuf {
+    match lance_path.split_once("://") {
+        Some((scheme, _)) if scheme != "file" => {
+            let key = content_hash(lance_path.trim_end_matches('/').as_bytes());
+            PathBuf::from(shellexpand::tilde(REMOTE_SLED_ROOT).as_ref()).join(format!(
+                "{}-{}",
+                scheme,
+                &key[..16]
+            ))
+        }
+        Some((_, path)) => Path::new(path).join(SLED_DIR),
+        None => Path::new(lance_path).join(SLED_DIR),
+    }
+}
+
+/// Local directory of a table's dataset.
+fn table_path(table: &Table) -> PathBuf {
+    let uri = table.dataset_uri();
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+/// Total size of all files below `path` (0 if it does not exist).
+async fn dir_size(path: PathBuf) -> Result<u64> {
+    fn walk(path: &Path) -> std::io::Result<u64> {
+        let mut total = 0;
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            total += if meta.is_dir() {
+                walk(&entry.path())?
+            } else {
+                meta.len()
+            };
+        }
+        Ok(total)
+    }
+    if !path.exists() {
+        return Ok(0);
+    }
+    Ok(tokio::task::spawn_blocking(move || walk(&path)).await??)
+}
+
+/// Read the model id (if recorded) and vector width from a table's schema.
+async fn stored_embedding(table: &Table) -> Result<(Option<String>, usize)> {
+    let schema = table.schema().await?;
+    let dim = match schema.field_with_name("vector")?.data_type() {
+        DataType::FixedSizeList(_, dim) => *dim as usize,
+        other => return Err(anyhow!("Unexpected vector column type {:?}", other)),
+    };
+    let model = schema.metadata().get(EMBEDDING_MODEL_KEY).cloned();
+    Ok((model, dim))
+}
+
+fn embedding_metadata(spec: &EmbeddingSpec) -> HashMap<String, String> {
+    HashMap::from([
+        (EMBEDDING_MODEL_KEY.to_string(), spec.model.clone()),
+        (EMBEDDING_DIM_KEY.to_string(), spec.dim.to_string()),
+    ])
+}
 
-        let schema = Arc::new(Schema::new(vec![
-            Field::new("id", DataType::Utf8, false),
-            Field::new("namespace", DataType::Utf8, false),
-            Field::new(
-                "vector",
-                DataType::FixedSizeList(
-                    Arc::new(Field::new("item", DataType::Float32, true)),
-                    dim as i32,
-                ),
-                false,
+fn table_schema(spec: &EmbeddingSpec) -> Arc<Schema> {
+    let schema = table_schema_for_dim(spec.dim);
+    Arc::new(
+        schema
+            .as_ref()
+            .clone()
+            .with_metadata(embedding_metadata(spec)),
+    )
+}
+
+fn table_schema_for_dim(dim: usize) -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("namespace", DataType::Utf8, false),
+        Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dim as i32,
             ),
-            Field::new("text", DataType::Utf8, true),
-            Field::new("metadata", DataType::Utf8, true),
-        ]));
-
-        let batch = RecordBatch::try_new(
-            schema.clone(),
-            vec![
-                Arc::new(StringArray::from(ids)),
-                Arc::new(StringArray::from(namespaces)),
-                Arc::new(
-                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
-                        vectors, dim as i32,
-                    ),
-                ),
-                Arc::new(StringArray::from(texts)),
-                Arc::new(StringArray::from(metadata_strings)),
-            ],
-        )?;
+            false,
+        ),
+        Field::new("text", DataType::Utf8, true),
+        Field::new("metadata", DataType::Utf8, true),
+        Field::new("updated_at", DataType::Int64, true),
+        Field::new("deleted_at", DataType::Int64, true),
+        Field::new("content_hash", DataType::Utf8, true),
+    ]))
+}
 
-        Ok(RecordBatchIterator::new(
-            vec![Ok(batch)].into_iter(),
-            schema,
-        ))
+/// Add columns introduced after a table was created so old stores keep working.
+async fn upgrade_schema(collection: &str, table: &Table) -> Result<()> {
+    let schema = table.schema().await?;
+    let missing = ADDED_COLUMNS
+        .iter()
+        .filter(|(name, _)| schema.field_with_name(name).is_err())
+        .map(|(name, expr)| (name.to_string(), expr.to_string()))
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return Ok(());
     }
+    info!(
+        "Upgrading collection '{}' with columns {:?}",
+        collection,
+        missing.iter().map(|(name, _)| name).collect::<Vec<_>>()
+    );
+    table
+        .add_columns(NewColumnTransform::SqlExpressions(missing), None)
+        .await?;
+    Ok(())
+}
 
-    fn batch_to_docs(&self, batch: &RecordBatch) -> Result<Vec<ChromaDocument>> {
-        let id_col = batch
-            .column_by_name("id")
-            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| anyhow!("Missing id column"))?;
-        let ns_col = batch
-            .column_by_name("namespace")
-            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| anyhow!("Missing namespace column"))?;
-        let text_col = batch
-            .column_by_name("text")
-            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| anyhow!("Missing text column"))?;
-        let metadata_col = batch
-            .column_by_name("metadata")
-            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
-            .ok_or_else(|| anyhow!("Missing metadata column"))?;
-        let vector_col = batch
-            .column_by_name("vector")
-            .and_then(|c| c.as_any().downcast_ref::<FixedSizeListArray>())
-            .ok_or_else(|| anyhow!("Missing vector column"))?;
-
-        let dim = vector_col.value_length() as usize;
-        let values = vector_col
-            .values()
-            .as_any()
-            .downcast_ref::<Float32Array>()
-            .ok_or_else(|| anyhow!("Vector inner type mismatch"))?;
-
-        let mut docs = Vec::new();
-        for i in 0..batch.num_rows() {
-            let id = id_col.value(i).to_string();
-            let text = text_col.value(i).to_string();
-            let namespace = ns_col.value(i).to_string();
-            let meta_str = metadata_col.value(i);
-            let metadata: Value = serde_json::from_str(meta_str).unwrap_or_else(|_| json!({}));
-
-            let offset = i * dim;
-            let mut emb = Vec::with_capacity(dim);
-            for j in 0..dim {
-                emb.push(values.value(offset + j));
+/// Current time in Unix milliseconds.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// RFC 3339 form of a Unix millisecond timestamp.
+pub fn format_millis(ms: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp_millis(ms).map(|t| t.to_rfc3339())
+}
+
+/// Split a re-embed generation table name (`{collection}__g{n}`) into its parts.
+fn generation_of(table: &str) -> Option<(&str, u64)> {
+    let (collection, generation) = table.rsplit_once("__g")?;
+    if collection.is_empty() || generation.is_empty() {
+        return None;
+    }
+    Some((collection, generation.parse().ok()?))
+}
+
+/// Collection names become Lance table names, which only allow a restricted charset.
+/// Names ending in `__g{n}` are reserved for the tables re-embedding builds.
+pub fn validate_collection_name(name: &str) -> Result<()> {
+    check_table_name(name)?;
+    if generation_of(name).is_some() {
+        return Err(anyhow!(
+            "Invalid collection name '{}': names ending in '__g' and a number are reserved for re-embedding",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn check_table_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("Collection name must not be empty"));
+    }
+    if name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(anyhow!(
+            "Invalid collection name '{}': use letters, digits, '_', '-' or '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use serde_json::json;
+
+    use super::{ChromaDocument, StorageManager};
+
+    /// A store in a fresh temporary directory, removed when the guard is dropped.
+    pub async fn temp_storage() -> (tempfile::TempDir, StorageManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lancedb");
+        let storage = StorageManager::new(16, &path.to_string_lossy())
+            .await
+            .unwrap();
+        (dir, storage)
+    }
+
+    pub fn doc(namespace: &str, id: &str, text: &str, embedding: Vec<f32>) -> ChromaDocument {
+        ChromaDocument {
+            id: id.to_string(),
+            namespace: namespace.to_string(),
+            embedding,
+            metadata: json!({}),
+            document: text.to_string(),
+            updated_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{doc, temp_storage};
+    use super::*;
+
+    /// Columns of tables written before any were added.
+    fn legacy_schema(dim: usize) -> Arc<Schema> {
+        let schema = table_schema_for_dim(dim);
+        let original = schema.fields()[..5].to_vec();
+        Arc::new(Schema::new(original))
+    }
+
+    #[tokio::test]
+    async fn writes_and_searches_with_another_model_are_rejected() {
+        let (_dir, storage) = temp_storage().await;
+        let small = EmbeddingSpec::new("small", 4);
+        storage
+            .add_to_store(None, vec![doc("ns", "a", "alpha", vec![1.0; 4])], &small)
+            .await
+            .unwrap();
+
+        let wide = EmbeddingSpec::new("wide", 8);
+        let err = storage
+            .add_to_store(None, vec![doc("ns", "b", "beta", vec![1.0; 8])], &wide)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("4-dim vectors from 'small'"));
+        let err = storage
+            .upsert_to_store(None, vec![doc("ns", "b", "beta", vec![1.0; 8])], &wide)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("re-embed"));
+
+        let renamed = EmbeddingSpec::new("other", 4);
+        let err = storage
+            .add_to_store(None, vec![doc("ns", "b", "beta", vec![1.0; 4])], &renamed)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("embedded with 'small'"));
+
+        assert!(storage
+            .search_store(None, Some("ns"), vec![1.0; 8], 5, &wide, None)
+            .await
+            .is_err());
+        assert!(storage
+            .search_store(None, Some("ns"), vec![1.0; 4], 5, &renamed, None)
+            .await
+            .is_err());
+        let found = storage
+            .search_store(None, Some("ns"), vec![1.0; 4], 5, &small, None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            storage.collection_embedding(None).await.unwrap(),
+            Some(small)
+        );
+    }
+
+    #[tokio::test]
+    async fn tables_without_metadata_record_the_first_matching_model() {
+        let (_dir, storage) = temp_storage().await;
+        storage
+            .lance
+            .create_empty_table("legacy", legacy_schema(4))
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.collection_embedding(Some("legacy")).await.unwrap(),
+            Some(EmbeddingSpec::new("unknown", 4))
+        );
+
+        // A different dimension is refused and records nothing.
+        let wide = EmbeddingSpec::new("wide", 8);
+        assert!(storage
+            .add_to_store(
+                Some("legacy"),
+                vec![doc("ns", "a", "alpha", vec![1.0; 8])],
+                &wide
+            )
+            .await
+            .is_err());
+        assert_eq!(
+            storage.collection_embedding(Some("legacy")).await.unwrap(),
+            Some(EmbeddingSpec::new("unknown", 4))
+        );
+
+        let small = EmbeddingSpec::new("small", 4);
+        storage
+            .add_to_store(
+                Some("legacy"),
+                vec![doc("ns", "a", "alpha", vec![1.0; 4])],
+                &small,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.collection_embedding(Some("legacy")).await.unwrap(),
+            Some(small.clone())
+        );
+        let table = storage.open_table("legacy").await.unwrap().unwrap();
+        let metadata = table.schema().await.unwrap().metadata().clone();
+        assert_eq!(metadata, embedding_metadata(&small));
+        assert!(storage
+            .add_to_store(
+                Some("legacy"),
+                vec![doc("ns", "b", "beta", vec![1.0; 4])],
+                &EmbeddingSpec::new("other", 4)
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn upgrade_backfills_the_same_content_hash_as_new_writes() {
+        let (_dir, storage) = temp_storage().await;
+        let texts = ["alpha", "", "héllo wörld ✓", "line one\nline two"];
+        let docs = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| doc("ns", &i.to_string(), text, vec![1.0; 4]))
+            .collect::<Vec<_>>();
+        let batch = docs_to_record_batch(&docs, 4, None)
+            .unwrap()
+            .project(&[0, 1, 2, 3, 4])
+            .unwrap();
+        let legacy = legacy_schema(4);
+        let batch = RecordBatch::try_new(legacy.clone(), batch.columns().to_vec()).unwrap();
+        storage
+            .lance
+            .create_table(
+                "legacy",
+                Box::new(RecordBatchIterator::new(vec![Ok(batch)], legacy)),
+            )
+            .execute()
+            .await
+            .unwrap();
+
+        let table = storage.open_table("legacy").await.unwrap().unwrap();
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec![
+                "text".to_string(),
+                "content_hash".to_string(),
+            ]))
+            .execute()
+            .await
+            .unwrap();
+        let mut checked = 0;
+        while let Some(batch) = stream.try_next().await.unwrap() {
+            let text = string_column(&batch, "text").unwrap();
+            let hash = string_column(&batch, "content_hash").unwrap();
+            for i in 0..batch.num_rows() {
+                assert_eq!(hash.value(i), content_hash(text.value(i).as_bytes()));
+                checked += 1;
             }
+        }
+        assert_eq!(checked, texts.len());
+    }
 
-            docs.push(ChromaDocument {
-                id,
-                namespace,
-                embedding: emb,
-                metadata,
-                document: text,
-            });
+    #[tokio::test]
+    async fn versions_count_live_rows_per_namespace() {
+        let (_dir, storage) = temp_storage().await;
+        let spec = EmbeddingSpec::new("small", 4);
+        let docs = vec![
+            doc("a", "1", "one", vec![1.0; 4]),
+            doc("a", "2", "two", vec![1.0; 4]),
+            doc("b", "3", "three", vec![1.0; 4]),
+        ];
+        storage.add_to_store(None, docs, &spec).await.unwrap();
+        storage.delete_document(None, "a", "1").await.unwrap();
+
+        let all = storage.list_versions(None, None, 10).await.unwrap();
+        assert_eq!(all[0].rows, 2);
+        assert_eq!(all[1].rows, 3);
+        assert!(all[0].version > all[1].version);
+
+        let a = storage.list_versions(None, Some("a"), 2).await.unwrap();
+        assert_eq!(a.len(), 2);
+        assert_eq!((a[0].rows, a[1].rows), (1, 2));
+        let b = storage.list_versions(None, Some("b"), 1).await.unwrap();
+        assert_eq!(b[0].rows, 1);
+    }
+
+    #[tokio::test]
+    async fn optimize_prunes_only_versions_past_the_retention_window() {
+        let (_dir, storage) = temp_storage().await;
+        let spec = EmbeddingSpec::new("small", 4);
+        for id in ["a", "b", "c"] {
+            storage
+                .add_to_store(None, vec![doc("ns", id, id, vec![1.0; 4])], &spec)
+                .await
+                .unwrap();
         }
-        Ok(docs)
+
+        let kept = storage
+            .optimize_collection(None, Some(Duration::from_secs(3600)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.old_versions_removed, 0);
+        assert!(kept.versions_after >= kept.versions_before);
+        assert!(kept.fragments_removed > 0);
+
+        let pruned = storage
+            .optimize_collection(None, Some(Duration::ZERO))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(pruned.old_versions_removed > 0);
+        assert_eq!(pruned.versions_after, 1);
+        assert_eq!(
+            storage.list_versions(None, None, 10).await.unwrap()[0].rows,
+            3
+        );
     }
 
-    fn namespace_filter(&self, namespace: &str) -> String {
-        format!("namespace = '{}'", namespace.replace('\'', "''"))
+    #[tokio::test]
+    async fn optimize_purges_only_expired_trash() {
+        let (_dir, storage) = temp_storage().await;
+        let storage = storage.with_trash_retention(Duration::from_secs(3600));
+        let spec = EmbeddingSpec::new("small", 4);
+        storage
+            .add_to_store(None, vec![doc("ns", "a", "alpha", vec![1.0; 4])], &spec)
+            .await
+            .unwrap();
+        storage.delete_document(None, "ns", "a").await.unwrap();
+
+        let report = storage
+            .optimize_collection(None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.trash_purged, 0);
+
+        let storage = storage.with_trash_retention(Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let report = storage
+            .optimize_collection(None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.trash_purged, 1);
+        assert!(storage.row_states(None, None).await.unwrap().is_empty());
     }
 
-    fn id_filter(&self, id: &str) -> String {
-        format!("id = '{}'", id.replace('\'', "''"))
+    #[test]
+    fn collection_names_are_validated() {
+        assert!(validate_collection_name("mcp_documents").is_ok());
+        assert!(validate_collection_name("project-a.v2").is_ok());
+        assert!(validate_collection_name("").is_err());
+        assert!(validate_collection_name(".hidden").is_err());
+        assert!(validate_collection_name("../escape").is_err());
+        assert!(validate_collection_name("with space").is_err());
+        assert!(validate_collection_name("notes__g1").is_err());
+        assert!(validate_collection_name("notes__gx").is_ok());
+        assert!(validate_collection_name("notes__g").is_ok());
+    }
+
+    #[tokio::test]
+    async fn generation_names_are_reserved_for_re_embedding() {
+        let (_dir, storage) = temp_storage().await;
+        let spec = EmbeddingSpec::new("small", 4);
+        let rows = || vec![doc("ns", "a", "alpha", vec![1.0; 4])];
+        let err = storage
+            .add_to_store(Some("notes__g1"), rows(), &spec)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("reserved"));
+
+        let staging = storage.staging_collection(Some("notes")).await.unwrap();
+        assert_eq!(staging, "notes__g1");
+        storage
+            .add_to_store(Some(&staging), rows(), &spec)
+            .await
+            .unwrap();
+        storage
+            .swap_collection(Some("notes"), &staging)
+            .await
+            .unwrap();
+        assert!(storage.row_states(Some(&staging), None).await.is_err());
+        assert_eq!(
+            storage.row_states(Some("notes"), None).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn remote_stores_keep_sled_on_local_disk() {
+        assert_eq!(
+            sled_path_for("/data/lancedb"),
+            Path::new("/data/lancedb").join(SLED_DIR)
+        );
+        assert_eq!(
+            sled_path_for("file:///data/lancedb"),
+            Path::new("/data/lancedb").join(SLED_DIR)
+        );
+
+        let root = PathBuf::from(shellexpand::tilde(REMOTE_SLED_ROOT).as_ref());
+        let s3 = sled_path_for("s3://bucket/memex");
+        assert!(s3.starts_with(&root));
+        assert!(s3.file_name().unwrap().to_string_lossy().starts_with("s3-"));
+        assert_eq!(s3, sled_path_for("s3://bucket/memex/"));
+        assert_ne!(s3, sled_path_for("s3://bucket/other"));
+        assert_ne!(s3, sled_path_for("gs://bucket/memex"));
+    }
+
+    #[test]
+    fn generation_tables_are_recognised() {
+        assert_eq!(generation_of("docs__g3"), Some(("docs", 3)));
+        assert_eq!(generation_of("my__group__g12"), Some(("my__group", 12)));
+        assert_eq!(generation_of("docs"), None);
+        assert_eq!(generation_of("docs__gx"), None);
+        assert_eq!(generation_of("__g1"), None);
     }
 }
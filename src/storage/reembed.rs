@@ -0,0 +1,155 @@
+use anyhow::Result;
+use arrow_array::{Array, Int64Array};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use std::collections::HashMap;
+
+use super::{batch_to_docs, delete_counted, string_column, ChromaDocument, StorageManager};
+
+/// Keys per filter when rows are addressed by key.
+const KEYS_PER_QUERY: usize = 500;
+
+/// Last write and trash time of one row, compared to keep a re-embed in step with its
+/// source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowState {
+    pub updated_at: Option<i64>,
+    pub deleted_at: Option<i64>,
+}
+
+impl StorageManager {
+    /// Like [`Self::stream_documents`], with trashed rows included.
+    pub async fn stream_all_documents(
+        &self,
+        collection: Option<&str>,
+    ) -> Result<BoxStream<'static, Result<Vec<ChromaDocument>>>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(futures::stream::empty().boxed());
+        };
+        let stream = table.query().execute().await?;
+        Ok(stream.map(|batch| batch_to_docs(&batch?)).boxed())
+    }
+
+    /// State of every row of a collection (optionally one namespace), trashed rows
+    /// included, read without loading vectors.
+    pub async fn row_states(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+    ) -> Result<HashMap<(String, String), RowState>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(HashMap::new());
+        };
+        let mut query = table.query().select(Select::Columns(vec![
+            "namespace".to_string(),
+            "id".to_string(),
+            "updated_at".to_string(),
+            "deleted_at".to_string(),
+        ]));
+        if let Some(ns) = namespace {
+            query = query.only_if(self.namespace_filter(ns));
+        }
+        let mut stream = query.execute().await?;
+        let mut states = HashMap::new();
+        while let Some(batch) = stream.try_next().await? {
+            let ns_col = string_column(&batch, "namespace")?;
+            let id_col = string_column(&batch, "id")?;
+            let updated_col = batch
+                .column_by_name("updated_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            let deleted_col = batch
+                .column_by_name("deleted_at")
+                .and_then(|c| c.as_any().downcast_ref::<Int64Array>());
+            let value = |col: Option<&Int64Array>, i: usize| {
+                col.filter(|c| c.is_valid(i)).map(|c| c.value(i))
+            };
+            for i in 0..batch.num_rows() {
+                states.insert(
+                    (ns_col.value(i).to_string(), id_col.value(i).to_string()),
+                    RowState {
+                        updated_at: value(updated_col, i),
+                        deleted_at: value(deleted_col, i),
+                    },
+                );
+            }
+        }
+        Ok(states)
+    }
+
+    /// Rows stored under the given keys, trashed rows included.
+    pub async fn documents_by_keys(
+        &self,
+        collection: Option<&str>,
+        keys: &[(String, String)],
+    ) -> Result<Vec<ChromaDocument>> {
+        let collection = self.resolve_collection(collection)?;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(Vec::new());
+        };
+        let mut documents = Vec::new();
+        for chunk in keys.chunks(KEYS_PER_QUERY) {
+            let mut stream = table
+                .query()
+                .only_if(self.keys_filter(chunk))
+                .execute()
+                .await?;
+            while let Some(batch) = stream.try_next().await? {
+                documents.append(&mut batch_to_docs(&batch)?);
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Give each key the trash time it is paired with; `None` makes the row live.
+    pub async fn set_trash_state(
+        &self,
+        collection: Option<&str>,
+        states: &[((String, String), Option<i64>)],
+    ) -> Result<()> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(());
+        };
+        let mut by_time: HashMap<Option<i64>, Vec<(String, String)>> = HashMap::new();
+        for (key, deleted_at) in states {
+            by_time.entry(*deleted_at).or_default().push(key.clone());
+        }
+        for (deleted_at, keys) in by_time {
+            let value = match deleted_at {
+                Some(ms) => ms.to_string(),
+                None => "CAST(NULL AS BIGINT)".to_string(),
+            };
+            for chunk in keys.chunks(KEYS_PER_QUERY) {
+                table
+                    .update()
+                    .only_if(self.keys_filter(chunk))
+                    .column("deleted_at", value.clone())
+                    .execute()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Permanently delete the given keys, bypassing the trash. Returns the rows removed.
+    pub async fn purge_keys(
+        &self,
+        collection: Option<&str>,
+        keys: &[(String, String)],
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(0);
+        };
+        let mut removed = 0;
+        for chunk in keys.chunks(KEYS_PER_QUERY) {
+            removed += delete_counted(&table, &self.keys_filter(chunk)).await?;
+        }
+        Ok(removed)
+    }
+}
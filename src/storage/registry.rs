@@ -0,0 +1,75 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::StorageManager;
+
+/// What the ingestion pipeline last stored for one source file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRecord {
+    pub path: String,
+    pub content_hash: String,
+    pub chunk_ids: Vec<String>,
+    /// File modification time in Unix milliseconds, when the filesystem reports one.
+    pub mtime: Option<i64>,
+    pub model: String,
+    pub indexed_at: i64,
+}
+
+/// Hex SHA-256 of `bytes`.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl StorageManager {
+    pub fn document_record(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        path: &str,
+    ) -> Result<Option<DocumentRecord>> {
+        let key = self.registry_key(collection, namespace, path)?;
+        match self.documents.get(key)? {
+            Some(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_document_record(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        record: &DocumentRecord,
+    ) -> Result<()> {
+        let key = self.registry_key(collection, namespace, &record.path)?;
+        self.documents.insert(key, serde_json::to_vec(record)?)?;
+        self.documents.flush()?;
+        Ok(())
+    }
+
+    fn registry_key(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        path: &str,
+    ) -> Result<String> {
+        let collection = self.resolve_collection(collection)?;
+        Ok(format!("{}\0{}\0{}", collection, namespace, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash_is_hex_sha256() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use futures::TryStreamExt;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use tracing::info;
+
+use super::{delete_counted, now_millis, string_column, StorageManager, LIVE_FILTER};
+
+impl StorageManager {
+    /// Bring trashed rows of a namespace back (all of them, or only `ids`). Rows whose key
+    /// has since been written again stay in the trash. Returns the number restored.
+    pub async fn restore_trashed(
+        &self,
+        collection: Option<&str>,
+        namespace: &str,
+        ids: Option<&[String]>,
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(0);
+        };
+        let mut predicate = format!(
+            "{} AND deleted_at IS NOT NULL",
+            self.namespace_filter(namespace)
+        );
+        if let Some(ids) = ids {
+            if ids.is_empty() {
+                return Ok(0);
+            }
+            predicate = format!(
+                "{} AND {}",
+                predicate,
+                self.id_list_filter(ids.iter().map(String::as_str))
+            );
+        }
+
+        let mut stream = table
+            .query()
+            .select(Select::Columns(vec!["id".to_string()]))
+            .only_if(predicate.as_str())
+            .execute()
+            .await?;
+        let mut keys = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let id_col = string_column(&batch, "id")?;
+            for i in 0..batch.num_rows() {
+                keys.push((namespace.to_string(), id_col.value(i).to_string()));
+            }
+        }
+        let live = self.existing_keys(Some(collection), &keys).await?;
+        keys.retain(|key| !live.contains(key));
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let predicate = format!("({}) AND deleted_at IS NOT NULL", self.keys_filter(&keys));
+
+        let restored = table
+            .update()
+            .only_if(predicate)
+            .column("deleted_at", "CAST(NULL AS BIGINT)")
+            .execute()
+            .await?
+            .rows_updated as usize;
+        info!(
+            "Restored {} trashed rows of namespace '{}' in '{}'",
+            restored, namespace, collection
+        );
+        Ok(restored)
+    }
+
+    /// Permanently delete trashed rows, optionally only those past the grace period and
+    /// only in one namespace. Returns the number removed.
+    pub async fn empty_trash(
+        &self,
+        collection: Option<&str>,
+        namespace: Option<&str>,
+        expired_only: bool,
+    ) -> Result<usize> {
+        let collection = self.resolve_collection(collection)?;
+        let _writing = self.writing(collection).await;
+        let Some(table) = self.open_table(collection).await? else {
+            return Ok(0);
+        };
+        let mut predicates = vec![format!("NOT ({})", LIVE_FILTER)];
+        if let Some(ns) = namespace {
+            predicates.push(self.namespace_filter(ns));
+        }
+        if expired_only {
+            let cutoff = now_millis() - self.trash_retention.as_millis() as i64;
+            predicates.push(format!("deleted_at < {}", cutoff));
+        }
+        let removed = delete_counted(&table, &predicates.join(" AND ")).await?;
+        if removed > 0 {
+            info!("Emptied {} trashed rows from '{}'", removed, collection);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::storage::mock::{doc, temp_storage};
+    use crate::storage::EmbeddingSpec;
+
+    #[tokio::test]
+    async fn deleted_rows_can_be_restored_until_the_trash_is_emptied() {
+        let (_dir, storage) = temp_storage().await;
+        let spec = EmbeddingSpec::new("small", 4);
+        let docs = vec![
+            doc("ns", "a", "alpha", vec![1.0; 4]),
+            doc("ns", "b", "beta", vec![1.0; 4]),
+        ];
+        storage.add_to_store(None, docs, &spec).await.unwrap();
+        storage.delete_document(None, "ns", "a").await.unwrap();
+        storage.delete_document(None, "ns", "b").await.unwrap();
+        assert!(storage
+            .get_document(None, "ns", "a", None)
+            .await
+            .unwrap()
+            .is_none());
+
+        let restored = storage
+            .restore_trashed(None, "ns", Some(&["a".to_string()]))
+            .await
+            .unwrap();
+        assert_eq!(restored, 1);
+        assert!(storage
+            .get_document(None, "ns", "a", None)
+            .await
+            .unwrap()
+            .is_some());
+
+        assert_eq!(
+            storage.empty_trash(None, Some("ns"), false).await.unwrap(),
+            1
+        );
+        assert_eq!(storage.restore_trashed(None, "ns", None).await.unwrap(), 0);
+        assert!(storage
+            .get_document(None, "ns", "b", None)
+            .await
+            .unwrap()
+            .is_none());
+        let a = storage
+            .get_document(None, "ns", "a", None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.document, "alpha");
+    }
+
+    #[tokio::test]
+    async fn only_trash_past_the_grace_period_expires() {
+        let (_dir, storage) = temp_storage().await;
+        let storage = storage.with_trash_retention(Duration::from_secs(3600));
+        let spec = EmbeddingSpec::new("small", 4);
+        storage
+            .add_to_store(None, vec![doc("ns", "a", "alpha", vec![1.0; 4])], &spec)
+            .await
+            .unwrap();
+        storage.delete_document(None, "ns", "a").await.unwrap();
+        assert_eq!(storage.empty_trash(None, None, true).await.unwrap(), 0);
+
+        let storage = storage.with_trash_retention(Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(storage.empty_trash(None, None, true).await.unwrap(), 1);
+        assert_eq!(storage.restore_trashed(None, "ns", None).await.unwrap(), 0);
+    }
+}
@@ -1,7 +1,6 @@
-use mcp_memex::{rag::RAGPipeline, storage::StorageManager};
+use mcp_memex::{embeddings::FastEmbedder, rag::RAGPipeline, storage::StorageManager};
 use serde_json::json;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 #[tokio::test]
 async fn memory_roundtrip_and_search() {
@@ -15,11 +14,12 @@ async fn memory_roundtrip_and_search() {
     );
     storage.ensure_collection().await.expect("collection");
 
-    let mlx = Arc::new(Mutex::new(None));
-    let rag = RAGPipeline::new(mlx, storage.clone()).await.expect("rag");
+    let embedder = Arc::new(FastEmbedder::new().expect("fastembed"));
+    let rag = RAGPipeline::new(embedder, storage.clone());
 
     // Upsert a memory chunk
     rag.memory_upsert(
+        None,
         "testns",
         "doc1".to_string(),
         "Ala ma kota".to_string(),
@@ -30,7 +30,7 @@ async fn memory_roundtrip_and_search() {
 
     // Read it back
     let fetched = rag
-        .memory_get("testns", "doc1")
+        .memory_get(None, "testns", "doc1")
         .await
         .expect("get")
         .expect("doc exists");
@@ -39,7 +39,7 @@ async fn memory_roundtrip_and_search() {
 
     // Semantic search within namespace
     let results = rag
-        .memory_search("testns", "kota", 1)
+        .memory_search(None, "testns", "kota", 1)
         .await
         .expect("search");
     assert!(!results.is_empty(), "expected at least one search result");